@@ -0,0 +1,237 @@
+use std::fmt;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+
+/// Zatoshis per ZEC.
+pub const COIN: u64 = 100_000_000;
+/// Total ZEC supply, the largest amount zcashd accepts.
+pub const MAX_MONEY: u64 = 21_000_000 * COIN;
+const DECIMALS: usize = 8;
+
+/// An exact, non-negative ZEC amount counted in zatoshis.
+///
+/// Amounts parse from and format as ZEC decimals ("0.0001") without going
+/// through floating point. They serialize to zcashd as decimal strings,
+/// which zcashd accepts wherever it takes an amount, and deserialize from
+/// either JSON numbers or strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub const fn from_zat(zat: u64) -> Self {
+        Amount(zat)
+    }
+
+    pub fn zat(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, n: u64) -> Option<Amount> {
+        self.0.checked_mul(n).map(Amount)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whole = self.0 / COIN;
+        let frac = self.0 % COIN;
+        if frac == 0 {
+            return write!(f, "{}", whole);
+        }
+        let frac = format!("{:0width$}", frac, width = DECIMALS);
+        write!(f, "{}.{}", whole, frac.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (whole, frac) = match s.split_once('.') {
+            Some((whole, frac)) => (whole, frac),
+            None => (s, ""),
+        };
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && frac.is_empty()) || !is_digits(whole) || !is_digits(frac) {
+            return Err(anyhow!("Invalid ZEC amount {:?}", s));
+        }
+        if frac.len() > DECIMALS {
+            return Err(anyhow!("ZEC amount {} has more than {} decimal places", s, DECIMALS));
+        }
+        let parse = |part: &str| if part.is_empty() { Ok(0) } else { part.parse::<u64>() };
+        let whole = parse(whole).map_err(|_| anyhow!("ZEC amount {} is too large", s))?;
+        let frac = parse(frac).unwrap_or(0) * 10u64.pow((DECIMALS - frac.len()) as u32);
+        whole.checked_mul(COIN)
+            .and_then(|zat| zat.checked_add(frac))
+            .filter(|zat| *zat <= MAX_MONEY)
+            .map(Amount)
+            .ok_or_else(|| anyhow!("ZEC amount {} is too large", s))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let zat = deserializer.deserialize_any(ZecVisitor)?;
+        u64::try_from(zat)
+            .map(Amount)
+            .map_err(|_| de::Error::custom(format!("negative ZEC amount {}", SignedAmount(zat))))
+    }
+}
+
+/// A ZEC amount that may be negative, such as the net effect of a wallet
+/// transaction reported by `gettransaction`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SignedAmount(i64);
+
+impl SignedAmount {
+    pub const fn from_zat(zat: i64) -> Self {
+        SignedAmount(zat)
+    }
+
+    pub fn zat(self) -> i64 {
+        self.0
+    }
+
+    /// The magnitude of the amount.
+    pub fn abs(self) -> Amount {
+        Amount(self.0.unsigned_abs())
+    }
+}
+
+impl fmt::Display for SignedAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", self.abs())
+    }
+}
+
+impl FromStr for SignedAmount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (neg, abs) = match s.strip_prefix('-') {
+            Some(abs) => (true, abs),
+            None => (false, s),
+        };
+        // Amounts are capped at MAX_MONEY, which always fits in an i64.
+        let zat = abs.parse::<Amount>()?.zat() as i64;
+        Ok(SignedAmount(if neg { -zat } else { zat }))
+    }
+}
+
+impl Serialize for SignedAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for SignedAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ZecVisitor).map(SignedAmount)
+    }
+}
+
+/// Reads a ZEC decimal, given as a JSON number or string, into zatoshis.
+struct ZecVisitor;
+
+impl<'de> Visitor<'de> for ZecVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a ZEC amount as a number or decimal string")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<i64, E> {
+        v.checked_mul(COIN)
+            .filter(|zat| *zat <= MAX_MONEY)
+            .map(|zat| zat as i64)
+            .ok_or_else(|| E::custom(format!("ZEC amount {} is too large", v)))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<i64, E> {
+        let zat = self.visit_u64::<E>(v.unsigned_abs())?;
+        Ok(if v < 0 { -zat } else { zat })
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<i64, E> {
+        // zcashd prints at most 8 decimals, so rounding to the nearest
+        // zatoshi recovers the exact amount.
+        let zat = (v * COIN as f64).round();
+        if !zat.is_finite() || zat.abs() > MAX_MONEY as f64 {
+            return Err(E::custom(format!("invalid ZEC amount {}", v)));
+        }
+        Ok(zat as i64)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<i64, E> {
+        v.parse::<SignedAmount>().map(SignedAmount::zat).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert!("0.0001".parse::<Amount>().unwrap() == Amount::from_zat(10_000));
+        assert!("1".parse::<Amount>().unwrap() == Amount::from_zat(COIN));
+        assert!("2.5".parse::<Amount>().unwrap() == Amount::from_zat(250_000_000));
+        assert!(".5".parse::<Amount>().unwrap() == Amount::from_zat(50_000_000));
+        assert!("0.00000001".parse::<Amount>().unwrap() == Amount::from_zat(1));
+        assert!("20999999.99999999".parse::<Amount>().unwrap() == Amount::from_zat(MAX_MONEY - 1));
+
+        assert!("0.000000001".parse::<Amount>().is_err());
+        assert!("-1".parse::<Amount>().is_err());
+        assert!("1e-4".parse::<Amount>().is_err());
+        assert!(".".parse::<Amount>().is_err());
+        assert!("".parse::<Amount>().is_err());
+        assert!("21000000.00000001".parse::<Amount>().is_err());
+        assert!("99999999999999999999".parse::<Amount>().is_err());
+
+        assert!(Amount::from_zat(10_000).to_string() == "0.0001");
+        assert!(Amount::from_zat(COIN).to_string() == "1");
+        assert!(Amount::from_zat(150_000_000).to_string() == "1.5");
+        assert!(Amount::ZERO.to_string() == "0");
+        assert!(SignedAmount::from_zat(-1_000_000).to_string() == "-0.01");
+        assert!("-0.01".parse::<SignedAmount>().unwrap() == SignedAmount::from_zat(-1_000_000));
+    }
+
+    #[test]
+    fn test_serde() {
+        // 0.1 and 2.99 have no exact binary representation.
+        assert!(serde_json::from_value::<Amount>(json!(0.1)).unwrap() == Amount::from_zat(10_000_000));
+        assert!(serde_json::from_value::<Amount>(json!(2.99)).unwrap() == Amount::from_zat(299_000_000));
+        assert!(serde_json::from_value::<Amount>(json!(3)).unwrap() == Amount::from_zat(300_000_000));
+        assert!(serde_json::from_value::<Amount>(json!("0.0001")).unwrap() == Amount::from_zat(10_000));
+        assert!(serde_json::from_value::<Amount>(json!(-0.5)).is_err());
+        assert!(serde_json::from_value::<SignedAmount>(json!(-0.5)).unwrap() == SignedAmount::from_zat(-50_000_000));
+
+        assert!(json!(Amount::from_zat(299_000_000)) == json!("2.99"));
+        assert!(json!(SignedAmount::from_zat(-1)) == json!("-0.00000001"));
+    }
+}
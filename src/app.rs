@@ -1,37 +1,285 @@
+use std::path::PathBuf;
+use regex::Regex;
 use structopt::StructOpt;
+use zmsg::amount::Amount;
+use zmsg::format::Format;
+use zmsg::network::Network;
+use zmsg::query::{Query, SortKey};
+use zmsg::rpc::{AddressType, PrivacyPolicy, SendOptions};
+use zmsg::store::Since;
 
+// Commands that talk to zcashd, and those that only touch zmsg's own
+// files and keep working while the node is down.
 #[derive(StructOpt)]
-#[structopt(rename_all = "snake_case")]
 pub enum Cmd {
-    /// Send a 512-byte encrypted memo to a target z_address
+    #[structopt(flatten)]
+    Zcashd(ZcashdCmd),
+    #[structopt(flatten)]
+    Local(LocalCmd),
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "snake_case")]
+pub enum ZcashdCmd {
+    /// Send an encrypted memo to a target z_address
     /// with a default spare ZEC of 0.0001 ZEC
     Sendmsg {
+        #[structopt(long, number_of_values = 1, required_unless = "to_file")]
+        /// a z_address or contact name of the recipient, repeat to broadcast to several
+        to: Vec<String>,
+        /// a file of recipient z_addresses or contact names, one per line
+        #[structopt(long = "to-file", parse(from_os_str))]
+        to_file: Option<PathBuf>,
+        /// ASCII or Unicode message, split over several memos past 512 bytes
+        msg: String,
+        /// Optional ZEC amount to be sent with the message
         #[structopt(long)]
-        /// a z_address of the recipient
-        to: String,
-        /// 512-byte max ASCII or Unicode message
+        txval: Option<Amount>,
+        /// a z_address or contact name the recipient can reply to, sent along in a zmsg envelope
+        #[structopt(long = "reply-to")]
+        reply_to: Option<String>,
+        /// a z_address or contact name to send from [default: the first one with enough funds]
+        #[structopt(long)]
+        from: Option<String>,
+        /// Wait until zcashd has built and broadcast the transaction
+        #[structopt(long)]
+        wait: bool,
+        #[structopt(flatten)]
+        send_opts: SendArgs,
+    },
+    /// Reply to a received message at the address its sender gave
+    Reply {
+        /// number of the message in check output, or a prefix of its txid
+        message: String,
+        /// ASCII or Unicode message, split over several memos past 512 bytes
         msg: String,
         /// Optional ZEC amount to be sent with the message
         #[structopt(long)]
-        txval: Option<f32>,
+        txval: Option<Amount>,
+        /// a z_address or contact name the recipient can reply to in turn
+        #[structopt(long = "reply-to")]
+        reply_to: Option<String>,
+        /// a z_address or contact name to send from [default: the first one with enough funds]
+        #[structopt(long)]
+        from: Option<String>,
+        /// Wait until zcashd has built and broadcast the transaction
+        #[structopt(long)]
+        wait: bool,
+        #[structopt(flatten)]
+        send_opts: SendArgs,
     },
-    /// Get my available shielded address(es)
+    /// Get my available shielded address(es), or create a new one
     Zaddr {
         #[structopt(short, long)]
         /// a z_address of the recipient
         all: bool,
+        /// Create a new receiving address
+        #[structopt(long, conflicts_with = "all")]
+        new: bool,
+        /// Type of the new address: sapling or unified [default: sapling]
+        #[structopt(long = "type", requires = "new")]
+        kind: Option<AddressType>,
+        /// A note kept locally with the new address, e.g. who it was given to
+        #[structopt(long, requires = "new")]
+        label: Option<String>,
+        /// Output format: pretty, json, ndjson or csv
+        #[structopt(long, default_value = "pretty")]
+        format: Format,
+    },
+    /// Show spendable and pending funds by address and pool
+    Balance {
+        /// Confirmations a note needs to count as spendable
+        #[structopt(long, default_value = "10")]
+        minconf: u32,
     },
     /// Check incoming messages
-    Check,
+    Check {
+        /// Show every message, not only unread ones
+        #[structopt(long)]
+        all: bool,
+        /// Scan again from a block height or a date (YYYY-MM-DD), and show every message since
+        #[structopt(long)]
+        since: Option<Since>,
+        /// Also show messages not mined yet, which may never be
+        #[structopt(long = "include-pending")]
+        include_pending: bool,
+        /// Output format: pretty, json, ndjson or csv
+        #[structopt(long, default_value = "pretty")]
+        format: Format,
+        #[structopt(flatten)]
+        query: QueryArgs,
+    },
+    /// Keep checking for messages and print new ones as they arrive
+    Watch {
+        /// Seconds to wait between checks
+        #[structopt(long, default_value = "30")]
+        interval: u64,
+    },
+    /// Show the messages exchanged with one correspondent as a transcript
+    Thread {
+        /// z_address or contact name of the correspondent
+        correspondent: String,
+    },
+    /// Show the status of a zcashd operation, e.g. a sent message
+    Opstatus {
+        /// operation id returned by sendmsg
+        opid: String,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "snake_case")]
+pub enum LocalCmd {
+    /// Archive a message so check no longer shows it
+    Archive {
+        /// number of the message in check output, or a prefix of its txid
+        message: String,
+        /// Move the message back out of the archive
+        #[structopt(long)]
+        undo: bool,
+    },
+    /// Manage the local address book
+    Contact {
+        #[structopt(subcommand)]
+        cmd: ContactCmd,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "snake_case")]
+pub enum ContactCmd {
+    /// Name a z_address so the name can be used in its place
+    Add {
+        name: String,
+        address: String,
+    },
+    /// List all contacts
+    List,
+    /// Remove a contact
+    Rm {
+        name: String,
+    },
+    /// Show the z_address of a contact
+    Show {
+        name: String,
+    },
+}
+
+// Fee and privacy controls shared by the commands that send messages.
+// (A doc comment here would replace the about text of those commands.)
+#[derive(StructOpt)]
+pub struct SendArgs {
+    /// Only spend notes with at least this many confirmations [default: 10]
+    #[structopt(long)]
+    pub minconf: Option<u32>,
+    /// Fee in ZEC [default: the ZIP-317 conventional fee]
+    #[structopt(long)]
+    pub fee: Option<Amount>,
+    /// zcashd privacy policy, e.g. FullPrivacy or AllowRevealedAmounts
+    #[structopt(long)]
+    pub privacy_policy: Option<PrivacyPolicy>,
+}
+
+// Filters, order and paging of the messages check lists.
+#[derive(StructOpt)]
+pub struct QueryArgs {
+    /// Only messages received on this z_address or contact
+    #[structopt(long)]
+    pub address: Option<String>,
+    /// Only messages from this block height or date (YYYY-MM-DD) on
+    #[structopt(long)]
+    pub after: Option<Since>,
+    /// Only messages before this block height or date (YYYY-MM-DD)
+    #[structopt(long)]
+    pub before: Option<Since>,
+    /// Only messages carrying at least this many ZEC
+    #[structopt(long)]
+    pub min_amount: Option<Amount>,
+    /// Only messages carrying at most this many ZEC
+    #[structopt(long)]
+    pub max_amount: Option<Amount>,
+    /// Only messages with at least this many confirmations
+    #[structopt(long)]
+    pub min_confs: Option<usize>,
+    /// Only messages whose text contains this
+    #[structopt(long)]
+    pub grep: Option<String>,
+    /// Only messages whose text matches this regular expression
+    #[structopt(long)]
+    pub regex: Option<Regex>,
+    /// Leave out messages with an empty memo
+    #[structopt(long)]
+    pub has_memo: bool,
+    /// Order messages by time, amount or height, instead of when they were first seen
+    #[structopt(long)]
+    pub sort: Option<SortKey>,
+    /// List messages in reverse order
+    #[structopt(long)]
+    pub reverse: bool,
+    /// Skip this many messages
+    #[structopt(long, default_value = "0")]
+    pub offset: usize,
+    /// Show at most this many messages
+    #[structopt(long)]
+    pub limit: Option<usize>,
 }
 
+impl From<QueryArgs> for Query {
+    fn from(args: QueryArgs) -> Self {
+        Query{
+            address: args.address,
+            after: args.after,
+            before: args.before,
+            min_amount: args.min_amount,
+            max_amount: args.max_amount,
+            min_confirmations: args.min_confs,
+            text: args.grep,
+            pattern: args.regex,
+            has_memo: args.has_memo,
+            sort: args.sort,
+            reverse: args.reverse,
+            offset: args.offset,
+            limit: args.limit,
+        }
+    }
+}
+
+impl From<SendArgs> for SendOptions {
+    fn from(args: SendArgs) -> Self {
+        SendOptions{
+            minconf: args.minconf,
+            fee: args.fee,
+            privacy_policy: args.privacy_policy,
+        }
+    }
+}
+
+impl Cmd {
+    /// Output format the command was asked for.
+    pub fn format(&self) -> Format {
+        match self {
+            Self::Zcashd(ZcashdCmd::Check{ format, .. })
+            | Self::Zcashd(ZcashdCmd::Zaddr{ format, .. }) => *format,
+            _ => Format::Pretty,
+        }
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Cmd {
     fn to_string(&self) -> String {
-	match self {
-            Self::Sendmsg{ .. } => String::from("sendmsg"),
-            Self::Zaddr{ .. } => String::from("zaddr"),
-            Self::Check => String::from("check"),
-	}
+        match self {
+            Self::Zcashd(ZcashdCmd::Sendmsg{ .. }) => String::from("sendmsg"),
+            Self::Zcashd(ZcashdCmd::Reply{ .. }) => String::from("reply"),
+            Self::Zcashd(ZcashdCmd::Zaddr{ .. }) => String::from("zaddr"),
+            Self::Zcashd(ZcashdCmd::Balance{ .. }) => String::from("balance"),
+            Self::Zcashd(ZcashdCmd::Check{ .. }) => String::from("check"),
+            Self::Zcashd(ZcashdCmd::Watch{ .. }) => String::from("watch"),
+            Self::Zcashd(ZcashdCmd::Thread{ .. }) => String::from("thread"),
+            Self::Zcashd(ZcashdCmd::Opstatus{ .. }) => String::from("opstatus"),
+            Self::Local(LocalCmd::Archive{ .. }) => String::from("archive"),
+            Self::Local(LocalCmd::Contact{ .. }) => String::from("contact"),
+        }
     }
 }
 
@@ -46,4 +294,26 @@ impl ToString for Cmd {
 pub struct Cli {
     #[structopt(subcommand)]
     pub cmd: Cmd,
+    /// zcashd config file, relative to the datadir [default: zcash.conf]
+    #[structopt(long, parse(from_os_str))]
+    pub conf: Option<PathBuf>,
+    /// zcashd data directory [default: ~/.zcash]
+    #[structopt(long, parse(from_os_str))]
+    pub datadir: Option<PathBuf>,
+    /// main, test or regtest [default: detected from zcash.conf]
+    #[structopt(long)]
+    pub network: Option<Network>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_pending() {
+        let cli = Cli::from_iter_safe(&["zmsg", "check", "--include-pending"])
+            .expect("Failed to parse --include-pending");
+        assert!(matches!(cli.cmd, Cmd::Zcashd(ZcashdCmd::Check{ include_pending: true, .. })));
+        assert!(Cli::from_iter_safe(&["zmsg", "check", "--include_pending"]).is_err());
+    }
 }
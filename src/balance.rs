@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::amount::Amount;
+use crate::network::Network;
+use crate::rpc::{AccountBalance, PoolBalance, TotalBalance};
+
+/// A value pool of the zcash chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pool {
+    Transparent,
+    Sprout,
+    Sapling,
+    Orchard,
+}
+
+impl Pool {
+    /// Pool of the funds sent to `addr`, or `None` for a unified address,
+    /// whose funds may sit in several.
+    pub fn of(addr: &str) -> Option<Self> {
+        let net = Network::ALL.iter().find(|net| net.accepts(addr))?;
+        match net.address_prefixes() {
+            [sapling, _, _, _, _] if addr.starts_with(sapling) => Some(Pool::Sapling),
+            [_, unified, _, _, _] if addr.starts_with(unified) => None,
+            [_, _, _, _, sprout] if addr.starts_with(sprout) => Some(Pool::Sprout),
+            _ => Some(Pool::Transparent),
+        }
+    }
+
+    pub fn is_shielded(self) -> bool {
+        self != Pool::Transparent
+    }
+}
+
+impl fmt::Display for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pool::Transparent => write!(f, "transparent"),
+            Pool::Sprout => write!(f, "sprout"),
+            Pool::Sapling => write!(f, "sapling"),
+            Pool::Orchard => write!(f, "orchard"),
+        }
+    }
+}
+
+/// Funds split by whether they can be spent yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Funds {
+    /// In notes with enough confirmations to be spent.
+    pub spendable: Amount,
+    /// In notes still waiting for confirmations.
+    pub pending: Amount,
+}
+
+impl Funds {
+    /// Funds given the balance counting only confirmed enough notes, and
+    /// the one counting every note.
+    pub fn new(confirmed: Amount, all: Amount) -> Self {
+        Funds {
+            spendable: confirmed,
+            pending: all.checked_sub(confirmed).unwrap_or(Amount::ZERO),
+        }
+    }
+
+    fn add(&mut self, other: Funds) {
+        self.spendable = self.spendable.checked_add(other.spendable).unwrap_or(self.spendable);
+        self.pending = self.pending.checked_add(other.pending).unwrap_or(self.pending);
+    }
+
+    /// How many payments costing `cost` the spendable funds cover.
+    pub fn affords(&self, cost: Amount) -> u64 {
+        match cost.zat() {
+            0 => 0,
+            zat => self.spendable.zat() / zat,
+        }
+    }
+}
+
+/// The wallet's funds by z_address, by account and by pool.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Balances {
+    pub addresses: Vec<(String, Funds)>,
+    pub accounts: Vec<(u32, BTreeMap<Pool, Funds>)>,
+    pub pools: BTreeMap<Pool, Funds>,
+}
+
+impl Balances {
+    /// Put together the balances zcashd reports. Each comes twice: counting
+    /// only notes with enough confirmations, and counting every note.
+    /// `total` covers the transparent funds, which belong to no z_address.
+    pub fn new(
+        addresses: Vec<(String, Amount, Amount)>,
+        accounts: Vec<(u32, AccountBalance, AccountBalance)>,
+        total: (TotalBalance, TotalBalance),
+    ) -> Self {
+        let mut balances = Balances::default();
+        balances.pools.insert(Pool::Transparent, Funds::new(total.0.transparent, total.1.transparent));
+
+        for (addr, confirmed, all) in addresses {
+            let funds = Funds::new(confirmed, all);
+            if let Some(pool) = Pool::of(&addr).filter(|pool| pool.is_shielded()) {
+                balances.pools.entry(pool).or_default().add(funds);
+            }
+            balances.addresses.push((addr, funds));
+        }
+
+        for (account, confirmed, all) in accounts {
+            let mut pools = BTreeMap::new();
+            let pool_funds = [
+                (Pool::Transparent, &confirmed.pools.transparent, &all.pools.transparent),
+                (Pool::Sapling, &confirmed.pools.sapling, &all.pools.sapling),
+                (Pool::Orchard, &confirmed.pools.orchard, &all.pools.orchard),
+            ];
+            for (pool, confirmed, all) in pool_funds {
+                let funds = Funds::new(pool_amount(confirmed), pool_amount(all));
+                if funds == Funds::default() {
+                    continue;
+                }
+                // Transparent funds of accounts are already in the total.
+                if pool.is_shielded() {
+                    balances.pools.entry(pool).or_default().add(funds);
+                }
+                pools.insert(pool, funds);
+            }
+            balances.accounts.push((account, pools));
+        }
+        balances
+    }
+
+    pub fn total(&self) -> Funds {
+        let mut total = Funds::default();
+        for funds in self.pools.values() {
+            total.add(*funds);
+        }
+        total
+    }
+
+    /// How many messages costing `cost` the shielded funds can pay for,
+    /// each being sent from a single address or account.
+    pub fn affords(&self, cost: Amount) -> u64 {
+        let addresses = self.addresses.iter().map(|(_, funds)| funds.affords(cost));
+        let accounts = self.accounts.iter().map(|(_, pools)| {
+            let mut shielded = Funds::default();
+            for (_, funds) in pools.iter().filter(|(pool, _)| pool.is_shielded()) {
+                shielded.add(*funds);
+            }
+            shielded.affords(cost)
+        });
+        addresses.chain(accounts).sum()
+    }
+}
+
+/// Whether `notes` can be split into groups paying for each of `costs`.
+///
+/// A message sent in several transactions can't spend the change of one
+/// in the next before it is confirmed, so each needs notes of its own.
+/// Largest notes go first to the largest need still open; this may miss
+/// a split that exists, but zcashd picks notes its own way anyway.
+pub fn pays_separately(notes: &[Amount], costs: &[Amount]) -> bool {
+    let mut notes = notes.iter().map(|note| note.zat()).collect::<Vec<_>>();
+    notes.sort_unstable_by(|a, b| b.cmp(a));
+    let mut needs = costs.iter().map(|cost| cost.zat()).collect::<Vec<_>>();
+    for note in notes {
+        match needs.iter_mut().filter(|need| **need > 0).max() {
+            Some(need) => *need = need.saturating_sub(note),
+            None => break,
+        }
+    }
+    needs.iter().all(|need| *need == 0)
+}
+
+fn pool_amount(balance: &Option<PoolBalance>) -> Amount {
+    Amount::from_zat(balance.as_ref().map_or(0, |balance| balance.value_zat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SAPLING: &str = "ztestsapling13vnh6svpvtpgntphha7nsafchx7zhhzu0gnptkztvahtvyueujwha2gcf976vt609qd8xjkaqwf";
+
+    fn zec(zat: u64) -> Amount {
+        Amount::from_zat(zat)
+    }
+
+    #[test]
+    fn test_pool_of() {
+        assert!(Pool::of(SAPLING) == Some(Pool::Sapling));
+        assert!(Pool::of("ztJ1EWLKcGwF2S4NA17pAJVdco8Sdkz4AQPxt1cLTEfNuyNswJJc2BbBqYrsRZsp31xbVZwhF7c7a2L9jsF3p3ZwRWpqqyS") == Some(Pool::Sprout));
+        assert!(Pool::of("utest1abc").is_none());
+        assert!(Pool::of("tmJ1EWLKcGwF2S4NA17pAJVdco8Sdkz4AQP") == Some(Pool::Transparent));
+        assert!(Pool::of("nope").is_none());
+    }
+
+    #[test]
+    fn test_balances() {
+        let total = |transparent: &str| serde_json::from_value::<TotalBalance>(json!({
+            "transparent": transparent, "private": "0", "total": transparent,
+        })).unwrap();
+        let account = |orchard: u64| serde_json::from_value::<AccountBalance>(json!({
+            "pools": { "orchard": { "valueZat": orchard } },
+            "minimum_confirmations": 1,
+        })).unwrap();
+
+        let balances = Balances::new(
+            vec![(SAPLING.to_string(), zec(50_000), zec(70_000))],
+            vec![(0, account(100_000), account(100_000))],
+            (total("0.001"), total("0.002")),
+        );
+        assert!(balances.addresses[0].1 == Funds{ spendable: zec(50_000), pending: zec(20_000) });
+        assert!(balances.accounts[0].1[&Pool::Orchard].spendable == zec(100_000));
+        assert!(balances.pools[&Pool::Sapling].pending == zec(20_000));
+        assert!(balances.pools[&Pool::Transparent].spendable == zec(100_000));
+        assert!(balances.total() == Funds{ spendable: zec(250_000), pending: zec(120_000) });
+        // 2 from the address, 5 from the account: transparent funds don't count.
+        assert!(balances.affords(zec(20_000)) == 7);
+    }
+
+    #[test]
+    fn test_pays_separately() {
+        let costs = [zec(30_000), zec(10_000)];
+        assert!(pays_separately(&[zec(100_000), zec(10_000)], &costs));
+        assert!(pays_separately(&[zec(20_000), zec(10_000), zec(10_000)], &costs));
+        // Enough in total, but a single note can only go to one transaction.
+        assert!(!pays_separately(&[zec(100_000)], &costs));
+        assert!(!pays_separately(&[zec(25_000), zec(15_000)], &costs));
+        assert!(pays_separately(&[], &[]));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+use anyhow::{anyhow, Error};
+use crate::hex::{Memo, MEMO_SIZE};
+
+/// Marks memos written by zmsg.
+pub(crate) const MAGIC: &[u8; 2] = b"ZM";
+const KIND_CHUNK: u8 = 0x01;
+/// magic, kind, message id, part index, total parts, payload length
+const HEADER_SIZE: usize = 2 + 1 + 8 + 1 + 1 + 2;
+/// Bytes of the message carried by each part, after the ZIP-302 lead byte
+/// and the header.
+pub const PAYLOAD_SIZE: usize = MEMO_SIZE - 1 - HEADER_SIZE;
+/// Parts are numbered with a single byte.
+pub const MAX_PARTS: usize = u8::MAX as usize;
+
+pub type MessageId = [u8; 8];
+
+/// One numbered part of a message too long for a single memo, sent as
+/// ZIP-302 arbitrary data so other wallets don't render it as text.
+///
+/// Layout after the 0xF5 lead byte:
+/// `'Z' 'M' 01 | id (8) | index (1) | total (1) | len (2, BE) | payload`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub msg_id: MessageId,
+    pub index: u8,
+    pub total: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Chunk {
+    /// The memo carrying this part.
+    pub fn to_memo(&self) -> Memo {
+        Memo::Arbitrary(self.encode())
+    }
+
+    /// Arbitrary data bytes of this part, without padding.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(KIND_CHUNK);
+        bytes.extend_from_slice(&self.msg_id);
+        bytes.push(self.index);
+        bytes.push(self.total);
+        bytes.extend_from_slice(&(self.payload.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Parse arbitrary memo data as a part, or `None` if it is not one.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || &data[0..2] != MAGIC || data[2] != KIND_CHUNK {
+            return None;
+        }
+        let mut msg_id = [0; 8];
+        msg_id.copy_from_slice(&data[3..11]);
+        let (index, total) = (data[11], data[12]);
+        let len = u16::from_be_bytes([data[13], data[14]]) as usize;
+        if index >= total || len > PAYLOAD_SIZE || HEADER_SIZE + len > data.len() {
+            return None;
+        }
+        Some(Chunk {
+            msg_id,
+            index,
+            total,
+            payload: data[HEADER_SIZE..HEADER_SIZE + len].to_vec(),
+        })
+    }
+}
+
+/// Split a message into parts sharing a fresh message id.
+pub fn split(msg: &[u8]) -> Result<Vec<Chunk>, Error> {
+    let total = msg.len().div_ceil(PAYLOAD_SIZE);
+    if total > MAX_PARTS {
+        return Err(anyhow!(
+            "Message is {} bytes, the limit is {} bytes", msg.len(), MAX_PARTS * PAYLOAD_SIZE,
+        ));
+    }
+    let msg_id = new_id(msg);
+    let chunks = msg.chunks(PAYLOAD_SIZE)
+        .enumerate()
+        .map(|(i, payload)| Chunk {
+            msg_id,
+            index: i as u8,
+            total: total as u8,
+            payload: payload.to_vec(),
+        })
+        .collect::<Vec<_>>();
+    Ok(chunks)
+}
+
+/// An id that is unique enough to tell apart the messages of one wallet.
+pub(crate) fn new_id(msg: &[u8]) -> [u8; 8] {
+    let mut hasher = DefaultHasher::new();
+    msg.hash(&mut hasher);
+    SystemTime::now().hash(&mut hasher);
+    std::process::id().hash(&mut hasher);
+    hasher.finish().to_be_bytes()
+}
+
+/// Memos carrying `text`: a single text memo when it fits, otherwise
+/// numbered parts.
+pub fn text_memos(text: &str) -> Result<Vec<Memo>, Error> {
+    if text.len() <= MEMO_SIZE {
+        return Ok(vec![Memo::Text(text.to_string())]);
+    }
+    data_memos(text.as_bytes())
+}
+
+/// Memos carrying `data`: a single arbitrary-data memo when it fits,
+/// otherwise numbered parts.
+pub fn data_memos(data: &[u8]) -> Result<Vec<Memo>, Error> {
+    if data.len() < MEMO_SIZE {
+        return Ok(vec![Memo::Arbitrary(data.to_vec())]);
+    }
+    Ok(split(data)?.iter().map(Chunk::to_memo).collect::<Vec<_>>())
+}
+
+/// Parts of one message collected so far, in any order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub total: u8,
+    parts: Vec<Option<Vec<u8>>>,
+}
+
+impl Assembly {
+    pub fn new(total: u8) -> Self {
+        Assembly {
+            total,
+            parts: vec![None; total as usize],
+        }
+    }
+
+    /// Record a part. Parts that disagree on the total are ignored.
+    pub fn add(&mut self, chunk: Chunk) {
+        if chunk.total == self.total {
+            self.parts[chunk.index as usize] = Some(chunk.payload);
+        }
+    }
+
+    /// Number of distinct parts received.
+    pub fn received(&self) -> usize {
+        self.parts.iter().filter(|part| part.is_some()).count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received() == self.total as usize
+    }
+
+    /// The received parts joined in order, skipping missing ones.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.parts.iter().flatten().flatten().copied().collect::<Vec<_>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_decode() {
+        let msg = "สวัสดีชาวโลก ".repeat(40);
+        let chunks = split(msg.as_bytes()).expect("Failed to split message");
+        assert!(chunks.len() == 3);
+        assert!(chunks.iter().all(|c| c.total == 3 && c.msg_id == chunks[0].msg_id));
+
+        for chunk in &chunks {
+            // zcashd pads memos with zeros.
+            let bytes = chunk.to_memo().to_bytes().expect("Part does not fit in a memo");
+            match Memo::from_bytes(&bytes).unwrap() {
+                Memo::Arbitrary(data) => assert!(Chunk::decode(&data).as_ref() == Some(chunk)),
+                memo => panic!("Expected arbitrary data, got {:?}", memo),
+            }
+        }
+
+        assert!(Chunk::decode(b"hello zcash").is_none());
+        assert!(split(&vec![b'a'; MAX_PARTS * PAYLOAD_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_assembly_out_of_order() {
+        let msg = "x".repeat(PAYLOAD_SIZE * 2 + 10);
+        let chunks = split(msg.as_bytes()).unwrap();
+
+        let mut assembly = Assembly::new(3);
+        assembly.add(chunks[2].clone());
+        assembly.add(chunks[0].clone());
+        assert!(!assembly.is_complete());
+        assert!(assembly.received() == 2);
+
+        assembly.add(chunks[0].clone());
+        assembly.add(chunks[1].clone());
+        assert!(assembly.is_complete());
+        assert!(assembly.bytes() == msg.as_bytes());
+    }
+}
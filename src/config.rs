@@ -0,0 +1,357 @@
+use std::{env, fs};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Error};
+use crate::network::Network;
+use crate::rpc::ZClientBuilder;
+
+/// User name zcashd writes in front of the token in its `.cookie` file.
+const COOKIE_USER: &str = "__cookie__";
+const CONF_FILENAME: &str = "zcash.conf";
+const DEFAULT_RPC_HOST: &str = "127.0.0.1";
+/// Guard against `includeconf` cycles.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Settings read from a zcash.conf file, resolved the way zcashd does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZcashConf {
+    /// Path of the top-level config file.
+    pub path: PathBuf,
+    /// zcashd's data directory (without the network subdirectory).
+    pub datadir: PathBuf,
+    /// Network selected by `testnet`/`regtest`, unless overridden.
+    pub network: Network,
+    /// Every `key=value` pair in file order, includes expanded in place.
+    entries: Vec<(String, String)>,
+}
+
+impl ZcashConf {
+    /// Load the config the way zcashd finds it: `datadir` defaults to
+    /// `~/.zcash` and `conf` to `zcash.conf`, resolved against the datadir.
+    /// A missing config file is only an error if it was asked for.
+    /// `network` overrides the network detected from the file.
+    pub fn load(
+        conf: Option<PathBuf>,
+        datadir: Option<PathBuf>,
+        network: Option<Network>,
+    ) -> Result<Self, Error> {
+        let datadir_given = datadir.is_some();
+        let datadir = match datadir {
+            Some(datadir) => datadir,
+            None => default_datadir()?,
+        };
+        let path = datadir.join(conf.clone().unwrap_or_else(|| PathBuf::from(CONF_FILENAME)));
+
+        let mut zconf = if path.exists() || conf.is_some() {
+            Self::parse(path, datadir)?
+        } else {
+            Self { path, datadir, network: Network::Main, entries: vec![] }
+        };
+        // Like zcashd, -datadir on the command line beats datadir= in the file.
+        if !datadir_given {
+            if let Some(datadir) = zconf.get("datadir") {
+                zconf.datadir = PathBuf::from(datadir);
+            }
+        }
+        if let Some(network) = network {
+            zconf.network = network;
+        }
+        Ok(zconf)
+    }
+
+    /// Read a single config file, taking its directory as the datadir
+    /// unless the file sets `datadir=`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut zconf = Self::parse(path, dir)?;
+        if let Some(datadir) = zconf.get("datadir") {
+            zconf.datadir = PathBuf::from(datadir);
+        }
+        Ok(zconf)
+    }
+
+    fn parse(path: PathBuf, datadir: PathBuf) -> Result<Self, Error> {
+        let mut entries = vec![];
+        read_entries(&path, &datadir, 0, &mut entries)?;
+        let mut zconf = Self { path, datadir, network: Network::Main, entries };
+        zconf.network = zconf.detect_network();
+        Ok(zconf)
+    }
+
+    /// Network named by the `regtest` and `testnet` options.
+    pub fn detect_network(&self) -> Network {
+        if self.flag("regtest") {
+            Network::Regtest
+        } else if self.flag("testnet") {
+            Network::Test
+        } else {
+            Network::Main
+        }
+    }
+
+    /// First value of `key`, which is the one zcashd uses.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of a repeatable key such as `rpcbind`.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.entries.iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>()
+    }
+
+    /// Whether a boolean option such as `testnet=1` is switched on.
+    pub fn flag(&self, key: &str) -> bool {
+        matches!(self.get(key), Some(v) if v != "0" && v != "false")
+    }
+
+    /// Host to reach the RPC server on: `rpcconnect`, else the first
+    /// specific `rpcbind` address, else localhost.
+    pub fn rpc_host(&self) -> String {
+        if let Some(host) = self.get("rpcconnect") {
+            return host.to_string();
+        }
+        self.get_all("rpcbind")
+            .into_iter()
+            .map(|bind| split_host_port(bind).0)
+            .find(|host| !matches!(*host, "0.0.0.0" | "::" | ""))
+            .unwrap_or(DEFAULT_RPC_HOST)
+            .to_string()
+    }
+
+    /// `rpcport`, else the port of the first `rpcbind` that names one,
+    /// else the default port of the configured network.
+    pub fn rpc_port(&self) -> Result<u16, Error> {
+        if let Some(port) = self.get("rpcport") {
+            return port.parse::<u16>().map_err(|_| anyhow!("Invalid rpcport={}", port));
+        }
+        let bind_port = self.get_all("rpcbind")
+            .into_iter()
+            .find_map(|bind| split_host_port(bind).1);
+        if let Some(port) = bind_port {
+            return port.parse::<u16>().map_err(|_| anyhow!("Invalid rpcbind port {}", port));
+        }
+        Ok(self.network.default_rpc_port())
+    }
+
+    /// URL of the zcashd JSON-RPC endpoint.
+    pub fn rpc_url(&self) -> Result<String, Error> {
+        let host = self.rpc_host();
+        let port = self.rpc_port()?;
+        if host.contains(':') {
+            Ok(format!("http://[{}]:{}", host.trim_matches(|c| c == '[' || c == ']'), port))
+        } else {
+            Ok(format!("http://{}:{}", host, port))
+        }
+    }
+
+    /// RPC user and password from the config file, or from the `.cookie`
+    /// file zcashd writes into the network's datadir.
+    pub fn credentials(&self) -> Result<(String, String), Error> {
+        if let (Some(user), Some(pass)) = (self.get("rpcuser"), self.get("rpcpassword")) {
+            if !user.is_empty() && !pass.is_empty() {
+                return Ok((user.to_string(), pass.to_string()));
+            }
+        }
+        let cookie_path = self.datadir.join(self.network.datadir_subdir()).join(".cookie");
+        read_cookie(&cookie_path).with_context(|| format!(
+            "No rpcuser/rpcpassword in {} and no usable cookie file at {}",
+            self.path.display(), cookie_path.display(),
+        ))
+    }
+
+    /// A client builder pointed at this node with its credentials.
+    pub fn client_builder(&self) -> Result<ZClientBuilder, Error> {
+        let (user, password) = self.credentials()?;
+        let builder = ZClientBuilder::default()
+            .with_url(self.rpc_url()?)?
+            .with_auth(user, Some(password));
+        Ok(builder)
+    }
+}
+
+/// Read the zcash.conf file to get RPC user and password.
+///
+/// When the file sets no `rpcuser`/`rpcpassword`, fall back to the
+/// `.cookie` file zcashd writes into its datadir (or the `testnet3` or
+/// `regtest` subdirectory of it).
+pub fn read_auth_creds(path: Option<OsString>) -> Result<(String, String), Error> {
+    match path {
+        Some(path) => ZcashConf::from_file(path)?.credentials(),
+        None => ZcashConf::load(None, None, None)?.credentials(),
+    }
+}
+
+fn default_datadir() -> Result<PathBuf, Error> {
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".zcash")),
+        None => Err(anyhow!("Failed to fetch $HOME. Did you set it?")),
+    }
+}
+
+/// Append the `key=value` pairs of a config file to `entries`, following
+/// `includeconf` (relative to the datadir) where it appears.
+fn read_entries(
+    path: &Path,
+    datadir: &Path,
+    depth: usize,
+    entries: &mut Vec<(String, String)>,
+) -> Result<(), Error> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(anyhow!("Too many nested includeconf at {}", path.display()));
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    for line in contents.lines() {
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        // Only the first '=' separates the key; values may contain more.
+        let (key, val) = match line.split_once('=') {
+            Some((key, val)) => (key.trim(), val.trim()),
+            None => continue,
+        };
+        if key.is_empty() {
+            continue;
+        }
+        if key == "includeconf" {
+            read_entries(&datadir.join(val), datadir, depth + 1, entries)?;
+        } else {
+            entries.push((key.to_string(), val.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Split `host[:port]`, also accepting bracketed IPv6 `[::1]:port`.
+fn split_host_port(addr: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = addr.strip_prefix('[') {
+        if let Some((host, tail)) = rest.split_once(']') {
+            return (host, tail.strip_prefix(':'));
+        }
+    }
+    match addr.rsplit_once(':') {
+        // A bare IPv6 address has several colons and no port.
+        Some((host, port)) if !host.contains(':') => (host, Some(port)),
+        _ => (addr, None),
+    }
+}
+
+/// Read the `__cookie__:<token>` credentials from a zcashd cookie file.
+fn read_cookie(path: &Path) -> Result<(String, String), Error> {
+    let cookie = fs::read_to_string(path)?;
+    match cookie.trim().split_once(':') {
+        Some((user, token)) if user == COOKIE_USER && !token.is_empty() => {
+            Ok((user.to_string(), token.to_string()))
+        },
+        _ => Err(anyhow!("Malformed cookie file {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
+    fn test_read_auth_creds() {
+        if let Ok((u, p)) = read_auth_creds(Some(OsString::from("./test_files/zcash.conf"))) {
+            assert!(u == "user");
+            assert!(p == "pass");
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn test_read_auth_creds_from_cookie() {
+        let (u, p) = read_auth_creds(Some(OsString::from("./test_files/cookie/zcash.conf")))
+            .expect("Failed to read the testnet cookie");
+        assert!(u == "__cookie__");
+        assert!(p == "0f1e2d3c4b5a69788796a5b4c3d2e1f0");
+    }
+
+    #[test]
+    fn test_read_auth_creds_missing() {
+        let err = read_auth_creds(Some(OsString::from("./test_files/nocreds/zcash.conf")))
+            .expect_err("Expected missing credentials to fail");
+        assert!(err.to_string().contains("no usable cookie file"));
+    }
+
+    #[test]
+    fn test_parse_full_conf() {
+        let zconf = ZcashConf::load(None, Some(PathBuf::from("./test_files/full")), None)
+            .expect("Failed to read test_files/full/zcash.conf");
+
+        // Comments, whitespace around '=' and '=' inside values.
+        assert!(zconf.get("rpcuser") == Some("alice"));
+        assert!(zconf.get("rpcpassword") == Some("s3cr3t==x"));
+        assert!(zconf.get("gen").is_none());
+        // First value wins, includes are expanded in place.
+        assert!(zconf.get("rpcport") == Some("28232"));
+        assert!(zconf.get_all("rpcbind") == vec!["0.0.0.0", "10.0.0.5:28000"]);
+        assert!(zconf.flag("testnet"));
+        assert!(!zconf.flag("regtest"));
+        assert!(zconf.network == Network::Test);
+
+        assert!(zconf.rpc_host() == "10.0.0.5");
+        assert!(zconf.rpc_url().unwrap() == "http://10.0.0.5:28232");
+        let (u, p) = zconf.credentials().unwrap();
+        assert!(u == "alice" && p == "s3cr3t==x");
+    }
+
+    #[test]
+    fn test_rpc_url_defaults() {
+        let zconf = ZcashConf::from_file("./test_files/zcash.conf").unwrap();
+        assert!(zconf.rpc_url().unwrap() == "http://127.0.0.1:18232");
+
+        let zconf = ZcashConf::from_file("./test_files/nocreds/zcash.conf").unwrap();
+        assert!(zconf.rpc_url().unwrap() == "http://127.0.0.1:8232");
+
+        let zconf = ZcashConf::load(None, Some(PathBuf::from("./test_files/nocreds")), Some(Network::Regtest))
+            .unwrap();
+        assert!(zconf.rpc_url().unwrap() == "http://127.0.0.1:8232");
+        assert!(zconf.network == Network::Regtest);
+
+        let zconf = ZcashConf::from_file("./test_files/cookie/zcash.conf").unwrap();
+        let builder = zconf.client_builder().expect("Failed to build client from config");
+        let client = builder.build();
+        assert!(client.url == "http://127.0.0.1:18232");
+        assert!(client.user == "__cookie__");
+    }
+
+    #[test]
+    fn test_client_builder_rereads_cookie() {
+        let datadir = env::temp_dir().join(format!("zmsg-cookie-{}", std::process::id()));
+        fs::create_dir_all(datadir.join("testnet3")).unwrap();
+        fs::write(datadir.join(CONF_FILENAME), "testnet=1\n").unwrap();
+        let cookie = datadir.join("testnet3").join(".cookie");
+        fs::write(&cookie, "__cookie__:before").unwrap();
+
+        let zconf = ZcashConf::load(None, Some(datadir.clone()), None).unwrap();
+        let client = zconf.client_builder().unwrap().build();
+        assert!(client.password.as_deref() == Some("before"));
+
+        // zcashd restarted with a new cookie.
+        fs::write(&cookie, "__cookie__:after").unwrap();
+        let client = zconf.client_builder().unwrap().build();
+        assert!(client.password.as_deref() == Some("after"));
+
+        fs::remove_dir_all(datadir).unwrap();
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert!(split_host_port("127.0.0.1") == ("127.0.0.1", None));
+        assert!(split_host_port("127.0.0.1:8232") == ("127.0.0.1", Some("8232")));
+        assert!(split_host_port("[::1]:8232") == ("::1", Some("8232")));
+        assert!(split_host_port("::1") == ("::1", None));
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Error};
+use serde::{Serialize, Deserialize};
+use crate::files::{config_dir, write_atomic};
+use crate::network::Network;
+
+const CONTACTS_FILENAME: &str = "contacts.json";
+
+/// A named z_address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub address: String,
+}
+
+/// The address book, kept as JSON in the zmsg config directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contacts {
+    path: PathBuf,
+    contacts: Vec<Contact>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ContactsFile {
+    contacts: Vec<Contact>,
+}
+
+impl Contacts {
+    /// Load the address book at `path`, which may not exist yet.
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let contacts = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<ContactsFile>(&json)
+                .with_context(|| format!("Failed to parse contacts in {}", path.display()))?
+                .contacts,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read contacts from {}", path.display()));
+            },
+        };
+        Ok(Contacts { path, contacts })
+    }
+
+    /// Load `contacts.json` from the zmsg config directory.
+    pub fn load_default() -> Result<Self, Error> {
+        Self::load(config_dir()?.join(CONTACTS_FILENAME))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let file = ContactsFile { contacts: self.contacts.clone() };
+        write_atomic(&self.path, &serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Failed to write contacts to {}", self.path.display()))
+    }
+
+    /// Add a contact. Names are unique and must not look like an address,
+    /// so that either can be given where an address is expected.
+    pub fn add(&mut self, name: &str, address: &str) -> Result<(), Error> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(anyhow!("Contact name {:?} must be a single word", name));
+        }
+        if Network::ALL.iter().any(|net| net.accepts(name)) {
+            return Err(anyhow!("Contact name {} looks like an address", name));
+        }
+        if self.get(name).is_some() {
+            return Err(anyhow!("Contact {} already exists", name));
+        }
+        self.contacts.push(Contact {
+            name: name.to_string(),
+            address: address.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Contact, Error> {
+        let i = self.contacts.iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| anyhow!("No contact named {}", name))?;
+        Ok(self.contacts.remove(i))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter()
+    }
+
+    /// Name of the contact with this address, if any.
+    pub fn name_of(&self, address: &str) -> Option<&str> {
+        self.contacts.iter()
+            .find(|c| c.address == address)
+            .map(|c| c.name.as_str())
+    }
+
+    /// The address of the contact named `name_or_addr`, or the argument
+    /// itself, taken to be an address.
+    pub fn resolve(&self, name_or_addr: &str) -> String {
+        match self.get(name_or_addr) {
+            Some(contact) => contact.address.clone(),
+            None => name_or_addr.to_string(),
+        }
+    }
+
+    /// An address for display, with the contact name when there is one.
+    pub fn label(&self, address: &str) -> String {
+        match self.name_of(address) {
+            Some(name) => format!("{} ({})", name, address),
+            None => address.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const BOB: &str = "ztestsapling13vnh6svpvtpgntphha7nsafchx7zhhzu0gnptkztvahtvyueujwha2gcf976vt609qd8xjkaqwf";
+
+    #[test]
+    fn test_contacts_roundtrip() {
+        let dir = env::temp_dir().join(format!("zmsg-contacts-{}", std::process::id()));
+        let path = dir.join(CONTACTS_FILENAME);
+
+        let mut contacts = Contacts::load(path.clone()).expect("Missing file should load as empty");
+        assert!(contacts.iter().next().is_none());
+        contacts.add("bob", BOB).unwrap();
+        assert!(contacts.add("bob", "zs1other").is_err());
+        assert!(contacts.add("big bob", BOB).is_err());
+        assert!(contacts.add("ztestsapling1xyz", BOB).is_err());
+        contacts.save().expect("Failed to save contacts");
+
+        let mut contacts = Contacts::load(path).unwrap();
+        assert!(contacts.resolve("bob") == BOB);
+        assert!(contacts.resolve("zs1other") == "zs1other");
+        assert!(contacts.name_of(BOB) == Some("bob"));
+        assert!(contacts.label(BOB) == format!("bob ({})", BOB));
+        assert!(contacts.remove("bob").unwrap().address == BOB);
+        assert!(contacts.remove("bob").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_contacts_parse_error() {
+        let dir = env::temp_dir().join(format!("zmsg-contacts-bad-{}", std::process::id()));
+        let path = dir.join(CONTACTS_FILENAME);
+        write_atomic(&path, "{ not json").unwrap();
+        assert!(Contacts::load(path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use crate::inbox::Message;
+
+/// Which side of a conversation wrote a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Messages exchanged with the correspondent at `addr`, oldest first.
+///
+/// Sent messages count when they were sent to `addr`. Memos don't name
+/// their sender, so received messages count when their envelope gives
+/// `addr` as the reply-to address, or links them to the conversation: by
+/// the thread id of a message already in it, or by answering one we sent.
+pub fn conversation(
+    received: Vec<Message>,
+    sent: Vec<Message>,
+    addr: &str,
+) -> Vec<(Direction, Message)> {
+    let sent = sent.into_iter()
+        .filter(|msg| msg.address == addr)
+        .collect::<Vec<_>>();
+    let (from_addr, others): (Vec<_>, Vec<_>) = received.into_iter()
+        .partition(|msg| msg.reply_to() == Some(addr));
+
+    let threads = sent.iter()
+        .chain(&from_addr)
+        .filter_map(|msg| msg.envelope()?.thread_id)
+        .collect::<HashSet<_>>();
+    let sent_txids = sent.iter().map(|msg| msg.txid.as_str()).collect::<HashSet<_>>();
+    let linked = others.into_iter()
+        .filter(|msg| match msg.envelope() {
+            Some(envelope) => {
+                envelope.thread_id.is_some_and(|id| threads.contains(&id))
+                    || envelope.in_reply_to.as_deref().is_some_and(|txid| sent_txids.contains(txid))
+            },
+            None => false,
+        })
+        .collect::<Vec<_>>();
+
+    let mut entries = sent.into_iter()
+        .map(|msg| (Direction::Sent, msg))
+        .chain(from_addr.into_iter().chain(linked).map(|msg| (Direction::Received, msg)))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(_, msg)| msg.time);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::envelope::Envelope;
+    use crate::inbox::Body;
+    use crate::hex::Memo;
+
+    fn message(txid: &str, address: &str, time: usize, body: Body) -> Message {
+        Message {
+            txid: txid.to_string(),
+            outindex: 0,
+            address: address.to_string(),
+            amount: Amount::from_zat(10_000),
+            time,
+            height: None,
+            msg_id: None,
+            body,
+        }
+    }
+
+    #[test]
+    fn test_conversation() {
+        let sent_txid = "ab".repeat(32);
+        let hello = Envelope::new("hello").with_reply_to("zs1me");
+        let sent = vec![
+            message(&sent_txid, "zs1bob", 100, Body::Envelope(hello.clone())),
+            message("tx_other", "zs1carol", 150, Body::Memo(Memo::Text("hi carol".to_string()))),
+        ];
+
+        // Bob answers twice: once with his address, once only in the thread.
+        let answer = Envelope::new("hi!").with_reply_to("zs1bob").in_reply_to(&sent_txid, &hello).unwrap();
+        let mut in_thread = Envelope::new("also...");
+        in_thread.thread_id = hello.thread_id;
+        let received = vec![
+            message("tx3", "zs1me", 300, Body::Envelope(in_thread)),
+            message("tx2", "zs1me", 200, Body::Envelope(answer)),
+            message("tx4", "zs1me", 250, Body::Memo(Memo::Text("faucet".to_string()))),
+            message("tx5", "zs1me", 260, Body::Envelope(Envelope::new("spam").with_reply_to("zs1eve"))),
+        ];
+
+        let entries = conversation(received, sent, "zs1bob");
+        let summary = entries.iter()
+            .map(|(dir, msg)| (*dir, msg.txid.as_str()))
+            .collect::<Vec<_>>();
+        assert!(summary == vec![
+            (Direction::Sent, sent_txid.as_str()),
+            (Direction::Received, "tx2"),
+            (Direction::Received, "tx3"),
+        ]);
+    }
+}
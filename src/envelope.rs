@@ -0,0 +1,251 @@
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use crate::chunk::{self, MAGIC};
+use crate::hex::Memo;
+
+const KIND_ENVELOPE: u8 = 0x02;
+/// Envelope layout understood by this version of zmsg.
+pub const VERSION: u8 = 1;
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// Field tags. Tag 0 ends the field list, so the zero padding of a memo
+/// reads as the end of the envelope.
+const TAG_END: u8 = 0x00;
+const TAG_REPLY_TO: u8 = 0x01;
+const TAG_THREAD_ID: u8 = 0x02;
+const TAG_CONTENT_TYPE: u8 = 0x03;
+const TAG_TIMESTAMP: u8 = 0x04;
+const TAG_BODY: u8 = 0x05;
+const TAG_IN_REPLY_TO: u8 = 0x06;
+
+pub type ThreadId = [u8; 8];
+
+/// A message together with the metadata a bare memo can't carry, such as
+/// where to send replies.
+///
+/// Envelopes are sent as ZIP-302 arbitrary data, split into parts when
+/// they don't fit in one memo. Layout after the 0xF5 lead byte:
+/// `'Z' 'M' 02 | version (1) | fields`, where each field is
+/// `tag (1) | len (2, BE) | value`. Fields with unknown tags are skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u8,
+    /// Address the sender wants replies sent to.
+    pub reply_to: Option<String>,
+    /// Shared by all messages of a conversation.
+    pub thread_id: Option<ThreadId>,
+    /// Txid of the message this one answers.
+    pub in_reply_to: Option<String>,
+    /// MIME type of the body.
+    pub content_type: String,
+    /// When the message was written, in seconds since the epoch.
+    pub timestamp: Option<u64>,
+    pub body: Vec<u8>,
+}
+
+impl Envelope {
+    /// A text message starting a new thread.
+    pub fn new(text: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        Envelope {
+            version: VERSION,
+            reply_to: None,
+            thread_id: Some(chunk::new_id(text.as_bytes())),
+            in_reply_to: None,
+            content_type: TEXT_PLAIN.to_string(),
+            timestamp,
+            body: text.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn with_reply_to(mut self, addr: &str) -> Self {
+        self.reply_to = Some(addr.to_string());
+        self
+    }
+
+    /// Mark this message as an answer to `parent`, received in the
+    /// transaction `txid`, and join its thread.
+    pub fn in_reply_to(mut self, txid: &str, parent: &Envelope) -> Result<Self, Error> {
+        if ::hex::decode(txid).map(|b| b.len()) != Ok(32) {
+            return Err(anyhow!("Invalid txid {}", txid));
+        }
+        self.in_reply_to = Some(txid.to_string());
+        if parent.thread_id.is_some() {
+            self.thread_id = parent.thread_id;
+        }
+        Ok(self)
+    }
+
+    /// The body, if it is text.
+    pub fn text(&self) -> Option<String> {
+        if self.content_type.starts_with("text/") {
+            Some(String::from_utf8_lossy(&self.body).into_owned())
+        } else {
+            None
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(KIND_ENVELOPE);
+        bytes.push(self.version);
+        if let Some(addr) = &self.reply_to {
+            push_field(&mut bytes, TAG_REPLY_TO, addr.as_bytes())?;
+        }
+        if let Some(id) = &self.thread_id {
+            push_field(&mut bytes, TAG_THREAD_ID, id)?;
+        }
+        if let Some(txid) = &self.in_reply_to {
+            // Txids are checked when set, and sent as their 32 raw bytes.
+            push_field(&mut bytes, TAG_IN_REPLY_TO, &::hex::decode(txid)?)?;
+        }
+        push_field(&mut bytes, TAG_CONTENT_TYPE, self.content_type.as_bytes())?;
+        if let Some(ts) = self.timestamp {
+            push_field(&mut bytes, TAG_TIMESTAMP, &ts.to_be_bytes())?;
+        }
+        push_field(&mut bytes, TAG_BODY, &self.body)?;
+        Ok(bytes)
+    }
+
+    /// Parse arbitrary memo data, or a reassembled long message, as an
+    /// envelope. Returns `None` for anything else, including envelopes of
+    /// a newer version.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 || &data[0..2] != MAGIC || data[2] != KIND_ENVELOPE {
+            return None;
+        }
+        let version = data[3];
+        if version == 0 || version > VERSION {
+            return None;
+        }
+        let mut envelope = Envelope {
+            version,
+            reply_to: None,
+            thread_id: None,
+            in_reply_to: None,
+            content_type: TEXT_PLAIN.to_string(),
+            timestamp: None,
+            body: vec![],
+        };
+
+        let mut rest = &data[4..];
+        while let Some((&tag, tail)) = rest.split_first() {
+            if tag == TAG_END {
+                break;
+            }
+            if tail.len() < 2 {
+                return None;
+            }
+            let len = u16::from_be_bytes([tail[0], tail[1]]) as usize;
+            let value = tail.get(2..2 + len)?;
+            match tag {
+                TAG_REPLY_TO => envelope.reply_to = Some(String::from_utf8(value.to_vec()).ok()?),
+                TAG_THREAD_ID => envelope.thread_id = Some(value.try_into().ok()?),
+                TAG_CONTENT_TYPE => envelope.content_type = String::from_utf8(value.to_vec()).ok()?,
+                TAG_TIMESTAMP => envelope.timestamp = Some(u64::from_be_bytes(value.try_into().ok()?)),
+                TAG_BODY => envelope.body = value.to_vec(),
+                TAG_IN_REPLY_TO => envelope.in_reply_to = Some(::hex::encode(value)),
+                _ => {},
+            }
+            rest = &tail[2 + len..];
+        }
+        Some(envelope)
+    }
+
+    /// Memos carrying this envelope.
+    pub fn to_memos(&self) -> Result<Vec<Memo>, Error> {
+        chunk::data_memos(&self.encode()?)
+    }
+}
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.text() {
+            Some(text) => write!(f, "{}", text),
+            None => write!(f, "[{} bytes of {}]", self.body.len(), self.content_type),
+        }
+    }
+}
+
+fn push_field(bytes: &mut Vec<u8>, tag: u8, value: &[u8]) -> Result<(), Error> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| anyhow!("Message field exceeds {} bytes", u16::MAX))?;
+    bytes.push(tag);
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex::MEMO_SIZE;
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = Envelope::new("สวัสดีชาวโลก").with_reply_to("zs1replyhere");
+        let memos = envelope.to_memos().expect("Failed to encode envelope");
+        assert!(memos.len() == 1);
+
+        // zcashd pads memos with zeros.
+        let bytes = memos[0].to_bytes().unwrap();
+        assert!(bytes.len() == MEMO_SIZE);
+        let data = match Memo::from_bytes(&bytes).unwrap() {
+            Memo::Arbitrary(data) => data,
+            memo => panic!("Expected arbitrary data, got {:?}", memo),
+        };
+        let decoded = Envelope::decode(&data).expect("Failed to decode envelope");
+        assert!(decoded == envelope);
+        assert!(decoded.reply_to.as_deref() == Some("zs1replyhere"));
+        assert!(decoded.to_string() == "สวัสดีชาวโลก");
+    }
+
+    #[test]
+    fn test_envelope_decode() {
+        assert!(Envelope::decode(b"hello zcash").is_none());
+
+        let mut envelope = Envelope::new("hi");
+        envelope.version = VERSION + 1;
+        assert!(Envelope::decode(&envelope.encode().unwrap()).is_none());
+
+        // Unknown fields are skipped, truncated ones rejected.
+        let mut bytes = Envelope::new("hi").encode().unwrap();
+        bytes.extend_from_slice(&[0x7f, 0, 2, 0xaa, 0xbb]);
+        assert!(Envelope::decode(&bytes).unwrap().text().as_deref() == Some("hi"));
+        bytes.truncate(bytes.len() - 1);
+        assert!(Envelope::decode(&bytes).is_none());
+
+        let mut binary = Envelope::new("");
+        binary.content_type = "application/octet-stream".to_string();
+        binary.body = vec![1, 2, 3];
+        assert!(binary.to_string() == "[3 bytes of application/octet-stream]");
+    }
+
+    #[test]
+    fn test_in_reply_to() {
+        let txid = "e1b2c3d4".repeat(8);
+        let parent = Envelope::new("how are you?").with_reply_to("zs1replyhere");
+        let reply = Envelope::new("fine").in_reply_to(&txid, &parent).unwrap();
+        assert!(reply.thread_id == parent.thread_id);
+
+        let decoded = Envelope::decode(&reply.encode().unwrap()).unwrap();
+        assert!(decoded.in_reply_to.as_deref() == Some(txid.as_str()));
+        assert!(decoded.thread_id == parent.thread_id);
+
+        assert!(Envelope::new("fine").in_reply_to("e1b2", &parent).is_err());
+    }
+
+    #[test]
+    fn test_long_envelope() {
+        let envelope = Envelope::new(&"z".repeat(1200)).with_reply_to("zs1replyhere");
+        let memos = envelope.to_memos().unwrap();
+        assert!(memos.len() == 3);
+        assert!(memos.iter().all(|memo| matches!(memo, Memo::Arbitrary(_))));
+    }
+}
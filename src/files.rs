@@ -0,0 +1,54 @@
+use std::{env, fs};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Error};
+
+/// Directory holding zmsg's own files: `$XDG_CONFIG_HOME/zmsg`, or
+/// `~/.config/zmsg`.
+pub fn config_dir() -> Result<PathBuf, Error> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir).join("zmsg"));
+    }
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".config").join("zmsg")),
+        None => Err(anyhow!("Failed to fetch $HOME. Did you set it?")),
+    }
+}
+
+/// Replace the file at `path` without leaving it half written. On unix
+/// only the user can read it, as the message store holds decrypted memos.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents.as_bytes())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_write_atomic_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("zmsg-files-{}", std::process::id()));
+        let path = dir.join("messages-testnet.json");
+        write_atomic(&path, "{}").unwrap();
+        write_atomic(&path, "{\"messages\": []}").unwrap();
+        assert!(fs::read_to_string(&path).unwrap() == "{\"messages\": []}");
+        assert!(fs::metadata(&path).unwrap().permissions().mode() & 0o777 == 0o600);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use serde_json::Value;
+use crate::hex::{trim_padding, Memo};
+use crate::inbox::{Body, Message};
+
+/// How a command prints its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The boxed layout meant for people.
+    Pretty,
+    /// One JSON array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Pretty, Format::Json, Format::Ndjson, Format::Csv];
+
+    /// Whether the output is meant for other programs, errors included.
+    pub fn is_structured(self) -> bool {
+        self != Format::Pretty
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Pretty => write!(f, "pretty"),
+            Format::Json => write!(f, "json"),
+            Format::Ndjson => write!(f, "ndjson"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::ALL.iter()
+            .find(|format| format.to_string() == s)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown format {}, expected one of pretty, json, ndjson or csv", s))
+    }
+}
+
+/// A row of structured output.
+pub trait Record: Serialize {
+    /// Names of the serialized fields, in the order of CSV columns.
+    const COLUMNS: &'static [&'static str];
+}
+
+/// A message as `check` reports it in structured formats.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageRecord {
+    /// Number of the message in the local store.
+    pub number: usize,
+    pub txid: String,
+    /// Our address the message was received on.
+    pub address: String,
+    pub amount_zat: u64,
+    pub height: Option<usize>,
+    pub confirmations: Option<usize>,
+    /// `pending`, `confirmed`, or `final` once a reorg can't undo it.
+    pub status: &'static str,
+    /// Seconds since the epoch.
+    pub time: usize,
+    /// The text of the message, or its bytes in hex when it is binary.
+    pub memo: Option<String>,
+    /// `text`, `hex`, or `incomplete` when parts are missing.
+    pub memo_format: &'static str,
+    pub reply_to: Option<String>,
+}
+
+impl MessageRecord {
+    /// Describe the message numbered `number`, with a chain tip at height
+    /// `tip`.
+    pub fn new(number: usize, msg: &Message, tip: usize) -> Self {
+        let (memo, memo_format) = match &msg.body {
+            Body::Memo(Memo::Text(text)) => (Some(text.clone()), "text"),
+            Body::Memo(Memo::Empty) => (Some(String::new()), "text"),
+            Body::Memo(Memo::Arbitrary(data)) | Body::Memo(Memo::Future(data)) => {
+                (Some(::hex::encode(trim_padding(data))), "hex")
+            },
+            Body::Envelope(envelope) => match envelope.text() {
+                Some(text) => (Some(text), "text"),
+                None => (Some(::hex::encode(&envelope.body)), "hex"),
+            },
+            Body::Incomplete { .. } => (None, "incomplete"),
+        };
+        MessageRecord {
+            number,
+            txid: msg.txid.clone(),
+            address: msg.address.clone(),
+            amount_zat: msg.amount.zat(),
+            height: msg.height,
+            confirmations: msg.confirmations(tip),
+            status: msg.status(tip).name(),
+            time: msg.time,
+            memo,
+            memo_format,
+            reply_to: msg.reply_to().map(str::to_string),
+        }
+    }
+}
+
+impl Record for MessageRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "number", "txid", "address", "amount_zat", "height", "confirmations",
+        "status", "time", "memo", "memo_format", "reply_to",
+    ];
+}
+
+/// One of the wallet's addresses, as `zaddr` reports it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AddressRecord {
+    pub address: String,
+    /// The label given to the address when it was created, if any.
+    pub label: Option<String>,
+}
+
+impl Record for AddressRecord {
+    const COLUMNS: &'static [&'static str] = &["address", "label"];
+}
+
+/// Write `records` in a structured format. Pretty output is left to each
+/// command, so it writes nothing.
+pub fn write_records<R: Record, W: Write>(
+    format: Format,
+    records: &[R],
+    mut out: W,
+) -> Result<(), Error> {
+    match format {
+        Format::Pretty => {},
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, records)?;
+            writeln!(out)?;
+        },
+        Format::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut out, record)?;
+                writeln!(out)?;
+            }
+        },
+        Format::Csv => {
+            writeln!(out, "{}", R::COLUMNS.join(","))?;
+            for record in records {
+                let value = serde_json::to_value(record)?;
+                let row = R::COLUMNS.iter()
+                    .map(|column| csv_field(&value[*column]))
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// A JSON value as a CSV field, quoted when it holds a separator, a quote
+/// or a line break.
+fn csv_field(value: &Value) -> String {
+    let field = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+
+    fn message(body: Body, height: Option<usize>) -> Message {
+        Message {
+            txid: "tx1".to_string(),
+            outindex: 0,
+            address: "zs1me".to_string(),
+            amount: Amount::from_zat(10_000),
+            time: 1620517682,
+            height,
+            msg_id: None,
+            body,
+        }
+    }
+
+    #[test]
+    fn test_message_record() {
+        let text = message(Body::Memo(Memo::Text("hi, \"you\"".to_string())), Some(100));
+        let record = MessageRecord::new(3, &text, 109);
+        assert!(record.confirmations == Some(10));
+        assert!(record.status == "final");
+        assert!(record.memo.as_deref() == Some("hi, \"you\""));
+        assert!(record.memo_format == "text");
+
+        let mut data = vec![0xca, 0xfe];
+        data.resize(511, 0);
+        let binary = message(Body::Memo(Memo::Arbitrary(data)), None);
+        let record = MessageRecord::new(4, &binary, 109);
+        assert!(record.confirmations.is_none());
+        assert!(record.status == "pending");
+        // Without the padding, as in the pretty output.
+        assert!(record.memo.as_deref() == Some("cafe"));
+        assert!(record.memo_format == "hex");
+
+        let mut data = vec![0xff, 0x01];
+        data.resize(512, 0);
+        let future = message(Body::Memo(Memo::Future(data)), Some(100));
+        assert!(MessageRecord::new(5, &future, 109).memo.as_deref() == Some("ff01"));
+    }
+
+    #[test]
+    fn test_write_records() {
+        let msg = message(Body::Memo(Memo::Text("hi, \"you\"\nbye".to_string())), Some(100));
+        let records = vec![MessageRecord::new(0, &msg, 102)];
+
+        let mut csv = vec![];
+        write_records(Format::Csv, &records, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv == concat!(
+            "number,txid,address,amount_zat,height,confirmations,status,time,memo,memo_format,reply_to\n",
+            "0,tx1,zs1me,10000,100,3,confirmed,1620517682,\"hi, \"\"you\"\"\nbye\",text,\n",
+        ));
+
+        let mut ndjson = vec![];
+        write_records(Format::Ndjson, &records, &mut ndjson).unwrap();
+        let line: Value = serde_json::from_slice(&ndjson).unwrap();
+        assert!(line["amount_zat"] == 10000);
+        assert!(line["confirmations"] == 3);
+
+        assert!("ndjson".parse::<Format>().unwrap() == Format::Ndjson);
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
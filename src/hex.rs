@@ -1,8 +1,16 @@
+use std::fmt;
 use std::str;
 use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+
+/// Size of a shielded memo field.
+pub const MEMO_SIZE: usize = 512;
+/// Lead bytes defined by ZIP-302. Anything below marks UTF-8 text.
+const ARBITRARY_DATA: u8 = 0xF5;
+const NO_MEMO: u8 = 0xF6;
 
 /// Convert a unicode string to a hexadecimal string.
-pub fn str_to_hex(s: &str) -> Result<String, Error> {    
+pub fn str_to_hex(s: &str) -> Result<String, Error> {
     let bmsg = s.as_bytes();
     if bmsg.len() > 512 {
         return Err(anyhow!("Message exceeds 512 bytes!"));
@@ -10,15 +18,108 @@ pub fn str_to_hex(s: &str) -> Result<String, Error> {
     Ok(hex::encode(s))
 }
 
-/// Convert a hexadecimal string to the corresponding unicode string.
+/// Convert a hexadecimal string to the corresponding unicode string,
+/// dropping the zero padding zcashd adds to memos.
 pub fn hex_to_string(s: &str) -> Result<String, Error> {
-    if let Ok(v) = hex::decode(s) {
-        return Ok(str::from_utf8(&v).unwrap().to_owned());
+    let v = hex::decode(s).map_err(|_| anyhow!("Fail to convert hex string to unicode string"))?;
+    let text = str::from_utf8(trim_padding(&v))
+        .map_err(|_| anyhow!("Memo is not valid UTF-8 text"))?;
+    Ok(text.to_owned())
+}
+
+/// Convert raw memo bytes to a hexadecimal string.
+pub fn bytes_to_hex(b: &[u8]) -> Result<String, Error> {
+    if b.len() > 512 {
+        return Err(anyhow!("Memo exceeds 512 bytes!"));
     }
-    Err(anyhow!("Fail to convert hex string to unicode string"))
+    Ok(hex::encode(b))
+}
+
+/// Convert a hexadecimal string to the raw bytes it encodes.
+pub fn hex_to_bytes(s: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(s).map_err(|_| anyhow!("Fail to convert hex string to bytes"))
+}
+
+/// The content of a memo field, interpreted as ZIP-302 describes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Memo {
+    /// UTF-8 text, without its zero padding.
+    Text(String),
+    /// The sender left no memo.
+    Empty,
+    /// Application data following the 0xF5 lead byte, up to 511 bytes.
+    Arbitrary(Vec<u8>),
+    /// A memo in a format reserved for future use, kept as is.
+    Future(Vec<u8>),
+}
+
+impl Memo {
+    /// Interpret the bytes of a memo field. Shorter input is treated as
+    /// if it were zero padded to 512 bytes.
+    pub fn from_bytes(b: &[u8]) -> Result<Self, Error> {
+        if b.len() > MEMO_SIZE {
+            return Err(anyhow!("Memo exceeds {} bytes!", MEMO_SIZE));
+        }
+        let memo = match b.first() {
+            None => Memo::Empty,
+            Some(lead) if *lead < ARBITRARY_DATA => {
+                // Invalid UTF-8 is still shown, with replacement characters.
+                Memo::Text(String::from_utf8_lossy(trim_padding(b)).into_owned())
+            },
+            Some(&ARBITRARY_DATA) => Memo::Arbitrary(b[1..].to_vec()),
+            Some(&NO_MEMO) if b[1..].iter().all(|byte| *byte == 0) => Memo::Empty,
+            Some(_) => Memo::Future(b.to_vec()),
+        };
+        Ok(memo)
+    }
+
+    /// The full 512-byte memo field.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut b = match self {
+            Memo::Text(text) => text.as_bytes().to_vec(),
+            Memo::Empty => vec![NO_MEMO],
+            Memo::Arbitrary(data) => [&[ARBITRARY_DATA], data.as_slice()].concat(),
+            Memo::Future(b) => b.clone(),
+        };
+        if b.len() > MEMO_SIZE {
+            return Err(anyhow!("Memo exceeds {} bytes!", MEMO_SIZE));
+        }
+        b.resize(MEMO_SIZE, 0);
+        Ok(b)
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        Self::from_bytes(&hex_to_bytes(s)?)
+    }
+
+    /// Hex form of the full memo field, as `z_sendmany` takes it.
+    pub fn to_hex(&self) -> Result<String, Error> {
+        bytes_to_hex(&self.to_bytes()?)
+    }
+}
+
+impl fmt::Display for Memo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Memo::Text(text) => write!(f, "{}", text),
+            Memo::Empty => Ok(()),
+            Memo::Arbitrary(data) => {
+                write!(f, "[{} bytes of binary data] {}", data.len(), hex::encode(trim_padding(data)))
+            },
+            Memo::Future(_) => write!(f, "[memo in a format reserved for future use]"),
+        }
+    }
+}
+
+/// `b` without the zero padding that fills a memo up to 512 bytes.
+pub fn trim_padding(b: &[u8]) -> &[u8] {
+    let len = b.iter().rposition(|byte| *byte != 0).map_or(0, |i| i + 1);
+    &b[..len]
 }
 
 #[cfg(test)]
+#[allow(unused_mut, unused_variables, unused_assignments)]
+#[allow(clippy::assertions_on_constants, clippy::len_zero)]
 mod tests {
     use super::*;
     
@@ -67,5 +168,50 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let s = bytes_to_hex(&[0xf5, 0x00, 0xff]).expect("Failed to encode bytes");
+        assert!(s == "f500ff");
+        assert!(hex_to_bytes(&s).unwrap() == vec![0xf5, 0x00, 0xff]);
+        assert!(bytes_to_hex(&[0; 513]).is_err());
+        assert!(hex_to_bytes("f5z").is_err());
+    }
+
+    #[test]
+    fn test_memo_decode() {
+        let mut padded = "hello zcash".as_bytes().to_vec();
+        padded.resize(MEMO_SIZE, 0);
+        assert!(Memo::from_bytes(&padded).unwrap() == Memo::Text("hello zcash".to_string()));
+        assert!(hex_to_string(&hex::encode(&padded)).unwrap() == "hello zcash");
+
+        let mut empty = vec![0xf6];
+        empty.resize(MEMO_SIZE, 0);
+        assert!(Memo::from_bytes(&empty).unwrap() == Memo::Empty);
+        empty[7] = 1;
+        assert!(matches!(Memo::from_bytes(&empty).unwrap(), Memo::Future(_)));
+
+        assert!(Memo::from_bytes(&[0xf5, 1, 2]).unwrap() == Memo::Arbitrary(vec![1, 2]));
+        assert!(matches!(Memo::from_bytes(&[0xff]).unwrap(), Memo::Future(_)));
+        assert!(Memo::from_bytes(&[0x61, 0xc3, 0x28]).unwrap() == Memo::Text("a\u{fffd}(".to_string()));
+        assert!(Memo::from_bytes(&[0; 513]).is_err());
+
+        // Non UTF-8 memos used to panic.
+        assert!(hex_to_string("f5c328").is_err());
+    }
+
+    #[test]
+    fn test_memo_encode() {
+        let memo = Memo::Text("สวัสดีชาวโลก".to_string());
+        let b = memo.to_bytes().unwrap();
+        assert!(b.len() == MEMO_SIZE);
+        assert!(Memo::from_hex(&memo.to_hex().unwrap()).unwrap() == memo);
+
+        assert!(Memo::Empty.to_bytes().unwrap()[0] == 0xf6);
+        let data = Memo::Arbitrary(vec![0xab; 511]);
+        assert!(Memo::from_bytes(&data.to_bytes().unwrap()).unwrap() == data);
+        assert!(Memo::Arbitrary(vec![0; 512]).to_bytes().is_err());
+        assert!(Memo::Text("a".repeat(513)).to_hex().is_err());
+    }
 }
 
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fmt;
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use crate::amount::Amount;
+use crate::chunk::{Assembly, Chunk, MessageId};
+use crate::envelope::Envelope;
+use crate::hex::Memo;
+use crate::rpc::{Tx, ViewOutput, WalletTx};
+use crate::store::REORG_DEPTH;
+
+/// A note carrying a memo, received by the wallet or sent from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub txid: String,
+    /// Index of the note's output in the transaction.
+    pub outindex: usize,
+    /// Our address the note was received on, or the recipient of a sent one.
+    pub address: String,
+    pub amount: Amount,
+    /// Memo field in hex, as zcashd reports it.
+    pub memo: String,
+    /// Time of the transaction, in seconds since the epoch.
+    pub time: usize,
+    /// Height of the block holding the transaction, if it was mined.
+    pub height: Option<usize>,
+}
+
+impl Note {
+    /// A note from `z_listreceivedbyaddress`, with the wallet transaction
+    /// holding it.
+    pub fn received(tx: Tx, address: String, wtx: &WalletTx) -> Self {
+        Self::received_at(tx, address, wtx.time)
+    }
+
+    /// A received note whose transaction time is already known.
+    pub fn received_at(tx: Tx, address: String, time: usize) -> Self {
+        Note {
+            txid: tx.txid,
+            outindex: tx.outindex,
+            address,
+            amount: tx.amount,
+            memo: tx.memo,
+            time,
+            height: tx.blockheight,
+        }
+    }
+
+    /// An output of `z_viewtransaction` the wallet sent, if zcashd could
+    /// tell its recipient.
+    pub fn sent(txid: &str, output: ViewOutput, time: usize) -> Option<Self> {
+        Some(Note {
+            txid: txid.to_string(),
+            outindex: output.output.unwrap_or(0),
+            address: output.address?,
+            amount: output.value,
+            memo: output.memo,
+            time,
+            height: None,
+        })
+    }
+}
+
+/// A received message, carried by one note or reassembled from several.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    /// Transaction of the first note seen for this message.
+    pub txid: String,
+    /// Output of that note, which together with `txid` identifies the
+    /// message.
+    pub outindex: usize,
+    /// Our address the message was received on, or its recipient when
+    /// the wallet sent it.
+    pub address: String,
+    /// Value of all notes carrying the message.
+    pub amount: Amount,
+    /// Time of the earliest note, in seconds since the epoch.
+    pub time: usize,
+    /// Height of the lowest block holding one of its notes, if any was
+    /// mined.
+    #[serde(default)]
+    pub height: Option<usize>,
+    /// Id shared by the parts of a long message, which identifies it
+    /// whichever of its notes was seen first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<MessageId>,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Body {
+    /// A memo that is not part of a longer message, or a complete one
+    /// reassembled into text.
+    Memo(Memo),
+    /// A message sent with zmsg metadata.
+    Envelope(Envelope),
+    /// Some parts of a long message have not arrived yet.
+    Incomplete { received: usize, total: usize },
+}
+
+impl Message {
+    pub fn envelope(&self) -> Option<&Envelope> {
+        match &self.body {
+            Body::Envelope(envelope) => Some(envelope),
+            _ => None,
+        }
+    }
+
+    /// Where the sender asked for replies to go, if they said.
+    pub fn reply_to(&self) -> Option<&str> {
+        self.envelope().and_then(|envelope| envelope.reply_to.as_deref())
+    }
+
+    /// Confirmations of the message's earliest note, given the height of
+    /// the chain tip, or `None` while it is in the mempool.
+    pub fn confirmations(&self, tip: usize) -> Option<usize> {
+        self.height.map(|height| (tip + 1).saturating_sub(height))
+    }
+
+    pub fn status(&self, tip: usize) -> Status {
+        match self.confirmations(tip) {
+            None | Some(0) => Status::Pending,
+            Some(n) if n >= REORG_DEPTH => Status::Final,
+            Some(n) => Status::Confirmed(n),
+        }
+    }
+}
+
+/// How settled a received message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// In the mempool, and may never be mined.
+    Pending,
+    /// Mined in a block with this many confirmations, which a reorg could
+    /// still undo.
+    Confirmed(usize),
+    /// Deeper than `REORG_DEPTH` blocks.
+    Final,
+}
+
+impl Status {
+    /// The status without its number of confirmations.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Confirmed(_) => "confirmed",
+            Self::Final => "final",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Confirmed(1) => write!(f, "confirmed (1 conf)"),
+            Self::Confirmed(n) => write!(f, "confirmed ({} confs)", n),
+            status => write!(f, "{}", status.name()),
+        }
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memo(memo) => write!(f, "{}", memo),
+            Self::Envelope(envelope) => write!(f, "{}", envelope),
+            Self::Incomplete { received, total } => {
+                write!(f, "[incomplete: {} of {} parts received]", received, total)
+            },
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    /// Interpret a complete long message.
+    fn from(bytes: Vec<u8>) -> Self {
+        match Envelope::decode(&bytes) {
+            Some(envelope) => Body::Envelope(envelope),
+            None => Body::Memo(Memo::Text(String::from_utf8_lossy(&bytes).into_owned())),
+        }
+    }
+}
+
+/// Turn notes into messages in the order they were first seen. Parts of a long message
+/// are merged by message id regardless of the order they arrived in, and
+/// notes without a memo are left out.
+pub fn assemble<I>(notes: I) -> Vec<Message>
+where
+    I: IntoIterator<Item = Note>,
+{
+    let mut messages: Vec<Message> = vec![];
+    let mut assemblies: HashMap<MessageId, (usize, Assembly)> = HashMap::new();
+
+    for note in notes {
+        let memo = Memo::from_hex(&note.memo).unwrap_or(Memo::Empty);
+        let (chunk, body) = match memo {
+            Memo::Empty => continue,
+            Memo::Arbitrary(data) => match (Chunk::decode(&data), Envelope::decode(&data)) {
+                (Some(chunk), _) => (Some(chunk), None),
+                (None, Some(envelope)) => (None, Some(Body::Envelope(envelope))),
+                (None, None) => (None, Some(Body::Memo(Memo::Arbitrary(data)))),
+            },
+            memo => (None, Some(Body::Memo(memo))),
+        };
+        let chunk = match (chunk, body) {
+            (Some(chunk), _) => chunk,
+            (None, body) => {
+                messages.push(Message {
+                    txid: note.txid,
+                    outindex: note.outindex,
+                    address: note.address,
+                    amount: note.amount,
+                    time: note.time,
+                    height: note.height,
+                    msg_id: None,
+                    body: body.unwrap_or(Body::Memo(Memo::Empty)),
+                });
+                continue;
+            },
+        };
+
+        if let Some((i, assembly)) = assemblies.get_mut(&chunk.msg_id) {
+            let msg = &mut messages[*i];
+            msg.amount = msg.amount.checked_add(note.amount).unwrap_or(msg.amount);
+            msg.time = msg.time.min(note.time);
+            msg.height = match (msg.height, note.height) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            assembly.add(chunk);
+            continue;
+        }
+        let mut assembly = Assembly::new(chunk.total);
+        let msg_id = chunk.msg_id;
+        assembly.add(chunk);
+        assemblies.insert(msg_id, (messages.len(), assembly));
+        messages.push(Message {
+            txid: note.txid,
+            outindex: note.outindex,
+            address: note.address,
+            amount: note.amount,
+            time: note.time,
+            height: note.height,
+            msg_id: Some(msg_id),
+            body: Body::Memo(Memo::Empty),
+        });
+    }
+
+    for (i, assembly) in assemblies.into_values() {
+        messages[i].body = if assembly.is_complete() {
+            Body::from(assembly.bytes())
+        } else {
+            Body::Incomplete {
+                received: assembly.received(),
+                total: assembly.total as usize,
+            }
+        };
+    }
+
+    messages
+}
+
+/// Look up a message by its number in `check` output, its position in
+/// `messages`, or by a prefix of its txid. Numbers take precedence over
+/// txids made only of digits. Returns the position of the message.
+pub fn find<'a, I>(messages: I, reference: &str) -> Result<usize, Error>
+where
+    I: IntoIterator<Item = &'a Message>,
+{
+    let messages = messages.into_iter().collect::<Vec<_>>();
+    if let Some(i) = reference.parse::<usize>().ok().filter(|i| *i < messages.len()) {
+        return Ok(i);
+    }
+    let reference = reference.to_lowercase();
+    let mut matches = messages.iter()
+        .enumerate()
+        .filter(|(_, msg)| msg.txid.starts_with(&reference))
+        .map(|(i, _)| i);
+    match (matches.next(), matches.next()) {
+        (Some(i), None) if !reference.is_empty() => Ok(i),
+        (Some(_), Some(_)) => Err(anyhow!("More than one message matches {}, give more of the txid", reference)),
+        _ => Err(anyhow!("No message numbered {} or with a txid starting with it", reference)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{self, PAYLOAD_SIZE};
+    use serde_json::json;
+
+    fn note(txid: &str, memo: String, time: usize) -> Note {
+        Note {
+            txid: txid.to_string(),
+            outindex: 0,
+            address: "zs1me".to_string(),
+            amount: Amount::from_zat(10_000),
+            memo,
+            time,
+            height: Some(time),
+        }
+    }
+
+    #[test]
+    fn test_received_note() {
+        let tx = serde_json::from_value(json!({
+            "txid": "tx1",
+            "amount": 0.0001,
+            "amountZat": 10000,
+            "memo": "f6",
+            "outindex": 0,
+            "confirmations": 3,
+            "blockheight": 100,
+            "blockindex": 1,
+            "blocktime": 100,
+            "change": false,
+        })).unwrap();
+        let wtx = serde_json::from_value(json!({
+            "status": "mined",
+            "txid": "tx1",
+            "amount": 0.0001,
+            "amountZat": 10000,
+            "confirmations": 3,
+            "blockhash": "00",
+            "blockindex": 1,
+            "blocktime": 100,
+            "time": 90,
+            "timereceived": 95,
+            "hex": "",
+            "details": [],
+            "vjoinsplit": [],
+        })).unwrap();
+        let note = Note::received(tx, "zs1me".to_string(), &wtx);
+        assert!(note == Note {
+            txid: "tx1".to_string(),
+            outindex: 0,
+            address: "zs1me".to_string(),
+            amount: Amount::from_zat(10_000),
+            memo: "f6".to_string(),
+            time: 90,
+            height: Some(100),
+        });
+    }
+
+    #[test]
+    fn test_assemble_out_of_order() {
+        let long = "a".repeat(PAYLOAD_SIZE) + "bc";
+        let parts = chunk::split(long.as_bytes()).unwrap();
+        let memo = |i: usize| parts[i].to_memo().to_hex().unwrap();
+
+        let messages = assemble(vec![
+            note("tx1", memo(1), 200),
+            note("tx2", Memo::Text("hello zcash".to_string()).to_hex().unwrap(), 150),
+            note("tx3", memo(0), 100),
+            note("tx4", Memo::Empty.to_hex().unwrap(), 300),
+        ]);
+        assert!(messages.len() == 2);
+        assert!(messages[0].body == Body::Memo(Memo::Text(long)));
+        assert!(messages[0].txid == "tx1");
+        assert!(messages[0].time == 100);
+        assert!(messages[0].height == Some(100));
+        assert!(messages[0].amount == Amount::from_zat(20000));
+        assert!(messages[1].body.to_string() == "hello zcash");
+    }
+
+    #[test]
+    fn test_assemble_incomplete() {
+        let long = "b".repeat(PAYLOAD_SIZE * 2 + 1);
+        let parts = chunk::split(long.as_bytes()).unwrap();
+        let memo = parts[2].to_memo().to_hex().unwrap();
+
+        let messages = assemble(vec![note("tx1", memo, 100)]);
+        assert!(messages[0].body == Body::Incomplete { received: 1, total: 3 });
+    }
+
+    #[test]
+    fn test_assemble_envelopes() {
+        let short = Envelope::new("hello").with_reply_to("zs1replyhere");
+        let long = Envelope::new(&"c".repeat(600)).with_reply_to("zs1other");
+        let mut notes = vec![note("tx1", short.to_memos().unwrap()[0].to_hex().unwrap(), 100)];
+        for (i, memo) in long.to_memos().unwrap().iter().enumerate().rev() {
+            notes.push(note(&format!("tx{}", i + 2), memo.to_hex().unwrap(), 200));
+        }
+
+        let messages = assemble(notes);
+        assert!(messages.len() == 2);
+        assert!(messages[0].reply_to() == Some("zs1replyhere"));
+        assert!(messages[0].body.to_string() == "hello");
+        assert!(messages[1].body == Body::Envelope(long));
+        assert!(messages[1].reply_to() == Some("zs1other"));
+    }
+
+    #[test]
+    fn test_status() {
+        let mut msg = assemble(vec![note("tx1", Memo::Text("hi".to_string()).to_hex().unwrap(), 100)]).remove(0);
+        assert!(msg.status(100) == Status::Confirmed(1));
+        assert!(msg.status(102).to_string() == "confirmed (3 confs)");
+        assert!(msg.status(100 + REORG_DEPTH - 1) == Status::Final);
+        msg.height = None;
+        assert!(msg.status(200) == Status::Pending);
+        assert!(msg.confirmations(200).is_none());
+    }
+
+    #[test]
+    fn test_find() {
+        let messages = assemble(vec![
+            note("ab12", Memo::Text("one".to_string()).to_hex().unwrap(), 100),
+            note("ab34", Memo::Text("two".to_string()).to_hex().unwrap(), 100),
+            note("9f00", Memo::Text("three".to_string()).to_hex().unwrap(), 100),
+        ]);
+        assert!(find(&messages, "1").unwrap() == 1);
+        assert!(find(&messages, "AB3").unwrap() == 1);
+        assert!(find(&messages, "9").unwrap() == 2);
+        assert!(find(&messages, "ab").is_err());
+        assert!(find(&messages, "cd").is_err());
+        assert!(find(&messages, "").is_err());
+    }
+}
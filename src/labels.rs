@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Error};
+use serde::{Serialize, Deserialize};
+use crate::files::{config_dir, write_atomic};
+
+const LABELS_FILENAME: &str = "labels.json";
+
+/// A note on one of our own addresses, e.g. who it was handed out to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    pub address: String,
+    pub label: String,
+}
+
+/// Labels of the wallet's addresses, kept as JSON in the zmsg config
+/// directory, since zcashd has no labels for shielded addresses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Labels {
+    path: PathBuf,
+    labels: Vec<Label>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LabelsFile {
+    labels: Vec<Label>,
+}
+
+impl Labels {
+    /// Load the labels at `path`, which may not exist yet.
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let labels = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<LabelsFile>(&json)
+                .with_context(|| format!("Failed to parse labels in {}", path.display()))?
+                .labels,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read labels from {}", path.display()));
+            },
+        };
+        Ok(Labels { path, labels })
+    }
+
+    /// Load `labels.json` from the zmsg config directory.
+    pub fn load_default() -> Result<Self, Error> {
+        Self::load(config_dir()?.join(LABELS_FILENAME))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let file = LabelsFile { labels: self.labels.clone() };
+        write_atomic(&self.path, &serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Failed to write labels to {}", self.path.display()))
+    }
+
+    /// Label `address`, replacing any label it had.
+    pub fn set(&mut self, address: &str, label: &str) -> Result<(), Error> {
+        check(label)?;
+        match self.labels.iter_mut().find(|l| l.address == address) {
+            Some(existing) => existing.label = label.to_string(),
+            None => self.labels.push(Label {
+                address: address.to_string(),
+                label: label.to_string(),
+            }),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, address: &str) -> Option<&str> {
+        self.labels.iter()
+            .find(|l| l.address == address)
+            .map(|l| l.label.as_str())
+    }
+}
+
+/// Check that `label` can be given to an address.
+pub fn check(label: &str) -> Result<(), Error> {
+    if label.trim().is_empty() {
+        return Err(anyhow!("Labels must not be empty"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const ADDR: &str = "ztestsapling13vnh6svpvtpgntphha7nsafchx7zhhzu0gnptkztvahtvyueujwha2gcf976vt609qd8xjkaqwf";
+
+    #[test]
+    fn test_labels_roundtrip() {
+        let dir = env::temp_dir().join(format!("zmsg-labels-{}", std::process::id()));
+        let path = dir.join(LABELS_FILENAME);
+
+        let mut labels = Labels::load(path.clone()).expect("Missing file should load as empty");
+        assert!(labels.get(ADDR).is_none());
+        labels.set(ADDR, "contact form").unwrap();
+        labels.set(ADDR, "newsletter signup").unwrap();
+        assert!(labels.set(ADDR, " ").is_err());
+        labels.save().expect("Failed to save labels");
+
+        let labels = Labels::load(path).unwrap();
+        assert!(labels.get(ADDR) == Some("newsletter signup"));
+        assert!(labels.get("zs1other").is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
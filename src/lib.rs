@@ -0,0 +1,16 @@
+pub mod amount;
+pub mod balance;
+pub mod chunk;
+pub mod config;
+pub mod contacts;
+pub mod conversation;
+pub mod envelope;
+pub mod files;
+pub mod format;
+pub mod hex;
+pub mod inbox;
+pub mod labels;
+pub mod network;
+pub mod query;
+pub mod rpc;
+pub mod store;
@@ -1,101 +1,937 @@
 mod app;
-mod hex;
-mod auth;
-mod rpc;
 
-use app::{Cmd, Cli};
-use std::string::ToString;
+use app::{Cmd, Cli, ContactCmd, LocalCmd, ZcashdCmd};
+use zmsg::{balance, chunk, config, contacts, conversation, envelope, hex, inbox, labels, rpc, store};
+use zmsg::format::{self, AddressRecord, MessageRecord};
+use zmsg::query::Query;
+use zmsg::conversation::Direction;
+use zmsg::amount::Amount;
 use structopt::StructOpt;
 use console::Term;
-use anyhow::{Result, Error};
-use chrono::{DateTime, NaiveDateTime, TimeZone, Local};
+use anyhow::{anyhow, Context, Result, Error};
+use chrono::{TimeZone, Local};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
 
-const DEFAULT_AMOUNT: f32 = 0.0001;
-const TESTNET_PORT: &str = "18232";
+const DEFAULT_AMOUNT: Amount = Amount::from_zat(10_000);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long `--wait` follows an operation before giving up on it.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(600);
+/// Bounds of the delay before `watch` tries to reach zcashd again.
+const RETRY_MIN: Duration = Duration::from_secs(2);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
 
 fn main() -> Result<(), Error> {
     let cli = Cli::from_args();
+    let format = cli.cmd.format();
+    let result = run(cli);
+    if let (Err(e), true) = (&result, format.is_structured()) {
+        let causes = e.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>();
+        eprintln!("{}", serde_json::json!({ "error": e.to_string(), "causes": causes }));
+        process::exit(1);
+    }
+    result
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let Cli{ cmd, conf, datadir, network } = cli;
+    let zconf = config::ZcashConf::load(conf, datadir, network)?;
+    match cmd {
+        Cmd::Zcashd(cmd) => run_zcashd(cmd, zconf),
+        Cmd::Local(cmd) => run_local(cmd, zconf),
+    }
+}
+
+/// Run a command that only touches zmsg's own files.
+fn run_local(cmd: LocalCmd, zconf: config::ZcashConf) -> Result<(), Error> {
     let term = Term::stdout();
-    let Cli{ cmd } = cli;
-
-    let (u, p) = auth::read_auth_creds(None)?;
-    let rpc_client = rpc::ZClient::builder()
-        .with_url(format!("http://127.0.0.1:{port}", port = TESTNET_PORT))?
-        .with_auth(u, Some(p))
-        .build();
-        
     match cmd {
-        Cmd::Sendmsg{ to, msg, .. } => {
-            let opid = send_msg_to(&rpc_client, &to, &msg, None)?;
-            let notify = format!("Message sent to {} with opid = {}", to, opid);
-	    let _ = term.write_line(&notify)?;
+        LocalCmd::Contact{ cmd } => {
+            let mut contacts = contacts::Contacts::load_default()?;
+            manage_contacts(cmd, &mut contacts, &zconf, &term)
         },
+        LocalCmd::Archive{ message, undo } => archive_message(&message, undo, &zconf, &term),
+    }
+}
 
-        Cmd::Zaddr{ all } => {
-            if all {
-                let addrs = rpc_client.z_listaddresses()?;
-                for addr in addrs {
-                    let _ = term.write_line(&addr)?;
+fn run_zcashd(cmd: ZcashdCmd, zconf: config::ZcashConf) -> Result<(), Error> {
+    let term = Term::stdout();
+    let rpc_client = zconf.client_builder()?.build();
+
+    match cmd {
+        ZcashdCmd::Sendmsg{ mut to, to_file, msg, txval, reply_to, from, wait, send_opts } => {
+            let contacts = contacts::Contacts::load_default()?;
+            if let Some(path) = to_file {
+                to.extend(read_recipients(&path)?);
+            }
+            let mut to = to.iter().map(|addr| contacts.resolve(addr)).collect::<Vec<_>>();
+            // zcashd rejects a transaction that pays the same address twice.
+            let mut seen = HashSet::new();
+            to.retain(|addr| seen.insert(addr.clone()));
+            if to.is_empty() {
+                return Err(anyhow!("No recipients given"));
+            }
+            for addr in &to {
+                zconf.network.validate_recipient(addr)?;
+            }
+
+            let memos = match reply_to.map(|addr| contacts.resolve(&addr)) {
+                Some(addr) => {
+                    zconf.network.validate_recipient(&addr)?;
+                    envelope::Envelope::new(&msg).with_reply_to(&addr).to_memos()?
+                },
+                None => chunk::text_memos(&msg)?,
+            };
+
+            let from = from.map(|addr| contacts.resolve(&addr));
+            if let Some(addr) = &from {
+                zconf.network.validate_address(addr)?;
+            }
+            let opids = send_msg_to(&rpc_client, from, &to, &memos, txval, &send_opts.into())?;
+            report_sent(&to, &opids, wait, &rpc_client, &contacts, &term)?;
+        },
+
+        ZcashdCmd::Reply{ message, msg, txval, reply_to, from, wait, send_opts } => {
+            let contacts = contacts::Contacts::load_default()?;
+            let mut store = store::Store::load_default(zconf.network)?;
+            sync_store(&rpc_client, &mut store, None, 1)?;
+            let i = inbox::find(store.messages(), &message)?;
+            let parent = &store.entries()[i].message;
+            let (parent_envelope, to) = match (parent.envelope(), parent.reply_to()) {
+                (Some(envelope), Some(addr)) => (envelope, addr.to_string()),
+                _ => return Err(anyhow!(
+                    "Message {} has no reply-to address, its sender did not say where to answer",
+                    message,
+                )),
+            };
+            zconf.network.validate_recipient(&to)?;
+
+            let mut envelope = envelope::Envelope::new(&msg).in_reply_to(&parent.txid, parent_envelope)?;
+            if let Some(addr) = reply_to.map(|addr| contacts.resolve(&addr)) {
+                zconf.network.validate_recipient(&addr)?;
+                envelope = envelope.with_reply_to(&addr);
+            }
+            let to = vec![to];
+            let from = from.map(|addr| contacts.resolve(&addr));
+            if let Some(addr) = &from {
+                zconf.network.validate_address(addr)?;
+            }
+            let opids = send_msg_to(&rpc_client, from, &to, &envelope.to_memos()?, txval, &send_opts.into())?;
+            report_sent(&to, &opids, wait, &rpc_client, &contacts, &term)?;
+        },
+
+        ZcashdCmd::Zaddr{ all, new, kind, label, format } => {
+            let mut labels = labels::Labels::load_default()?;
+            let addrs = if new {
+                if let Some(label) = &label {
+                    labels::check(label)?;
+                }
+                let addr = new_address(&rpc_client, kind.unwrap_or(rpc::AddressType::Sapling))?;
+                if let Some(label) = &label {
+                    labels.set(&addr, label)?;
+                    labels.save()?;
                 }
+                vec![addr]
             } else {
-                let addr = rpc_client.z_listaddresses()?[0].clone();
-                let _ = term.write_line(&addr);
+                let mut addrs = match all {
+                    true => wallet_addresses(&rpc_client)?,
+                    false => rpc_client.z_listaddresses()?,
+                };
+                if addrs.is_empty() {
+                    return Err(anyhow!("The wallet has no z_address yet, create one with zaddr --new"));
+                }
+                if !all {
+                    addrs.truncate(1);
+                }
+                addrs
+            };
+            if format.is_structured() {
+                let records = addrs.into_iter()
+                    .map(|address| {
+                        let label = labels.get(&address).map(str::to_string);
+                        AddressRecord{ address, label }
+                    })
+                    .collect::<Vec<_>>();
+                format::write_records(format, &records, io::stdout().lock())?;
+            } else {
+                for addr in addrs {
+                    match labels.get(&addr) {
+                        Some(label) => term.write_line(&format!("{} ({})", addr, label))?,
+                        None => term.write_line(&addr)?,
+                    }
+                }
             }
         },
 
-        Cmd::Check => {
-            let txs = rpc_client.z_listaddresses()?
+        ZcashdCmd::Balance{ minconf } => {
+            let contacts = contacts::Contacts::load_default()?;
+            let balances = fetch_balances(&rpc_client, minconf)?;
+            let cost = send_costs(1, 1, DEFAULT_AMOUNT, &rpc::SendOptions::default())?[0];
+            report_balances(&balances, minconf, cost, &contacts, &term)?;
+        },
+
+        ZcashdCmd::Check{ all, since, include_pending, format, query } => {
+            let contacts = contacts::Contacts::load_default()?;
+            let mut query = Query::from(query);
+            query.address = query.address.map(|addr| contacts.resolve(&addr));
+            let mut store = store::Store::load_default(zconf.network)?;
+            let minconf = if include_pending { 0 } else { 1 };
+            let tip = sync_store(&rpc_client, &mut store, since, minconf)?;
+            // A search looks through every message and leaves them unread.
+            let search = query.is_search();
+            let shown = store.entries()
                 .iter()
-                .flat_map(|addr| rpc_client.z_listreceivedbyaddress(&addr).unwrap())
-                .filter(|(tx, _)| !tx.change)
+                .enumerate()
+                .filter(|(_, entry)| match since {
+                    Some(since) => {
+                        let msg = &entry.message;
+                        since.includes(msg.height.unwrap_or(usize::MAX), msg.time)
+                    },
+                    None => all || search || entry.is_unread(),
+                })
+                .map(|(i, entry)| (i, &entry.message))
                 .collect::<Vec<_>>();
-            
-            let _ = report_txs(txs, &rpc_client, &term)?;
+            let shown = query.apply(shown, tip);
+            let heading = match all || since.is_some() || search {
+                true => format!("Got {} messages.", shown.len()),
+                false => format!("Got {} unread messages.", shown.len()),
+            };
+            if format.is_structured() {
+                let records = shown.iter()
+                    .map(|(i, msg)| MessageRecord::new(*i, msg, tip))
+                    .collect::<Vec<_>>();
+                format::write_records(format, &records, io::stdout().lock())?;
+            } else {
+                report_messages(&heading, &shown, tip, &contacts, &term)?;
+            }
+
+            if !search {
+                let shown = shown.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+                for i in shown {
+                    store.entries_mut()[i].read = true;
+                }
+            }
+            store.save()?;
+        },
+
+        ZcashdCmd::Watch{ interval } => {
+            let contacts = contacts::Contacts::load_default()?;
+            let mut store = store::Store::load_default(zconf.network)?;
+            watch(rpc_client, &zconf, &mut store, Duration::from_secs(interval), &contacts, &term)?;
+        },
+        ZcashdCmd::Thread{ correspondent } => {
+            let contacts = contacts::Contacts::load_default()?;
+            let correspondent = contacts.resolve(&correspondent);
+            zconf.network.validate_address(&correspondent)?;
+            let store = store::Store::load_default(zconf.network)?;
+            let notes = fetch_notes(&rpc_client, 1)?;
+            let sent = sent_messages(&rpc_client, &notes, &correspondent)?;
+            let received = received_messages(&rpc_client, notes, &store)?;
+            let entries = conversation::conversation(received, sent, &correspondent);
+            report_conversation(&correspondent, &entries, &contacts, &term)?;
+        },
+
+        ZcashdCmd::Opstatus{ opid } => {
+            let op = rpc_client.z_getoperationstatus(std::slice::from_ref(&opid))?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No operation with opid = {}", opid))?;
+            term.write_line(&format!("Operation {} is {}", op.id, op.status))?;
+            if op.is_finished() {
+                report_operation(&op, &term)?;
+            }
         },
     }
 
     Ok(())
 }
 
-fn report_txs(txs: Vec<(rpc::Tx, String)>, rpc_client: &rpc::ZClient, term: &Term) -> Result<(), Error> {
-    let num_msg = &txs.len();
+fn manage_contacts(
+    cmd: ContactCmd,
+    contacts: &mut contacts::Contacts,
+    zconf: &config::ZcashConf,
+    term: &Term,
+) -> Result<(), Error> {
+    match cmd {
+        ContactCmd::Add{ name, address } => {
+            zconf.network.validate_address(&address)?;
+            contacts.add(&name, &address)?;
+            contacts.save()?;
+            term.write_line(&format!("Added {} ({})", name, address))?;
+        },
+        ContactCmd::List => {
+            for contact in contacts.iter() {
+                term.write_line(&format!("{:<16} {}", contact.name, contact.address))?;
+            }
+        },
+        ContactCmd::Rm{ name } => {
+            let contact = contacts.remove(&name)?;
+            contacts.save()?;
+            term.write_line(&format!("Removed {} ({})", contact.name, contact.address))?;
+        },
+        ContactCmd::Show{ name } => {
+            let contact = contacts.get(&name)
+                .ok_or_else(|| anyhow!("No contact named {}", name))?;
+            term.write_line(&contact.address)?;
+        },
+    }
+    Ok(())
+}
+
+/// Check for new messages every `interval` until interrupted, printing
+/// unread ones as `check` does once they are complete. While zcashd can't
+/// be reached, retry after a delay that doubles up to `RETRY_MAX`.
+fn watch(
+    mut rpc_client: rpc::ZClient,
+    zconf: &config::ZcashConf,
+    store: &mut store::Store,
+    interval: Duration,
+    contacts: &contacts::Contacts,
+    term: &Term,
+) -> Result<(), Error> {
+    term.write_line(&format!(
+        "Checking for messages every {}s, press Ctrl-C to stop.", interval.as_secs(),
+    ))?;
+    let mut retry = RETRY_MIN;
+    loop {
+        let tip = match sync_store(&rpc_client, store, None, 1) {
+            Ok(tip) => tip,
+            Err(e) => match e.downcast_ref::<rpc::ZRpcError>() {
+                Some(err) if err.is_transient() => {
+                    term.write_line(&format!("{}, retrying in {}s", err, retry.as_secs()))?;
+                    thread::sleep(retry);
+                    retry = (retry * 2).min(RETRY_MAX);
+                    continue;
+                },
+                // A restarted zcashd has a new cookie, which may not be
+                // written yet, so keep trying until it can be read.
+                Some(err) if err.needs_reconnect() => {
+                    term.write_line(&format!("{}, reconnecting in {}s", err, retry.as_secs()))?;
+                    thread::sleep(retry);
+                    retry = (retry * 2).min(RETRY_MAX);
+                    match zconf.client_builder() {
+                        Ok(builder) => rpc_client = builder.build(),
+                        Err(e) => term.write_line(&format!("{:#}", e))?,
+                    }
+                    continue;
+                },
+                _ => return Err(e),
+            },
+        };
+        retry = RETRY_MIN;
+
+        let new = store.entries()
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                entry.is_unread() && !matches!(entry.message.body, inbox::Body::Incomplete{ .. })
+            })
+            .map(|(i, entry)| (i, &entry.message))
+            .collect::<Vec<_>>();
+        if !new.is_empty() {
+            report_messages(&format!("Got {} new messages.", new.len()), &new, tip, contacts, term)?;
+            let new = new.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+            for i in new {
+                store.entries_mut()[i].read = true;
+            }
+            store.save()?;
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Set or clear the archived flag of a stored message.
+fn archive_message(
+    reference: &str,
+    undo: bool,
+    zconf: &config::ZcashConf,
+    term: &Term,
+) -> Result<(), Error> {
+    let mut store = store::Store::load_default(zconf.network)?;
+    let i = inbox::find(store.messages(), reference)?;
+    let entry = &mut store.entries_mut()[i];
+    entry.archived = !undo;
+    let notify = match undo {
+        false => format!("Archived message #{} (txid = {})", i, entry.message.txid),
+        true => format!("Restored message #{} (txid = {})", i, entry.message.txid),
+    };
+    store.save()?;
+    term.write_line(&notify)?;
+    Ok(())
+}
+
+/// Notes received on all of the wallet's z_addresses and unified
+/// addresses, change included, with at least `minconf` confirmations.
+fn fetch_notes(rpc_client: &rpc::ZClient, minconf: u32) -> Result<Vec<(rpc::Tx, String)>, Error> {
+    let addrs = wallet_addresses(rpc_client)?;
+    Ok(rpc_client.z_listreceivedbyaddresses(&addrs, minconf)?)
+}
+
+/// Add the messages received on all of the wallet's addresses to the
+/// store, and save it. Only notes above the store's scanned height are
+/// read, or those from `since` on when it is given, along with notes
+/// still in the mempool when `minconf` is 0. Messages that left the
+/// mempool or the chain before they settled are dropped. Returns the
+/// height of the chain tip.
+fn sync_store(
+    rpc_client: &rpc::ZClient,
+    store: &mut store::Store,
+    since: Option<store::Since>,
+    minconf: u32,
+) -> Result<usize, Error> {
+    let tip = rpc_client.getblockcount()?;
+    // Pending notes are fetched too, so that messages zcashd no longer
+    // returns at all can be told from those not confirmed enough.
+    let notes = fetch_notes(rpc_client, 0)?;
+    store.prune(&notes, tip);
+    let notes = notes.into_iter()
+        .filter(|(tx, _)| tx.confirmations >= minconf as usize)
+        .collect();
+    let notes = store.unscanned(notes, since);
+
+    let mut messages = received_messages(rpc_client, notes, store)?;
+    // A scan from an arbitrary point may miss the first parts of a long
+    // message, or other notes, so only a full one keeps incomplete
+    // messages and moves the watermark.
+    if since.is_some() {
+        messages.retain(|msg| !matches!(msg.body, inbox::Body::Incomplete{ .. }));
+    }
+    store.sync(messages);
+    if since.is_none() {
+        store.advance(tip);
+    }
+    store.save()?;
+    Ok(tip)
+}
+
+/// Assemble the messages carried by received notes, leaving out change.
+/// Transaction times already in the store are not fetched again.
+fn received_messages(
+    rpc_client: &rpc::ZClient,
+    notes: Vec<(rpc::Tx, String)>,
+    store: &store::Store,
+) -> Result<Vec<inbox::Message>, Error> {
+    let txs = notes.into_iter()
+        .filter(|(tx, _)| !tx.change)
+        .collect::<Vec<_>>();
+
+    let mut times = txs.iter()
+        .filter_map(|(tx, _)| Some((tx.txid.clone(), store.time_of(&tx.txid)?)))
+        .collect::<HashMap<_, _>>();
+    let mut seen = HashSet::new();
+    let missing = txs.iter()
+        .map(|(tx, _)| tx.txid.clone())
+        .filter(|txid| !times.contains_key(txid) && seen.insert(txid.clone()))
+        .collect::<Vec<_>>();
+    for wtx in rpc_client.gettransactions(&missing)? {
+        times.insert(wtx.txid, wtx.time);
+    }
+
+    let notes = txs.into_iter()
+        .map(|(tx, a)| {
+            let time = times.get(&tx.txid).copied().unwrap_or_default();
+            inbox::Note::received_at(tx, a, time)
+        });
+    Ok(inbox::assemble(notes))
+}
+
+/// Messages the wallet sent to `addr`.
+///
+/// zcashd has no list of sent shielded transactions, so they are found
+/// through the change they paid back to the wallet. Sends that spent
+/// their notes exactly, leaving no change, are missed.
+fn sent_messages(
+    rpc_client: &rpc::ZClient,
+    notes: &[(rpc::Tx, String)],
+    addr: &str,
+) -> Result<Vec<inbox::Message>, Error> {
+    let mut seen = HashSet::new();
+    let txids = notes.iter()
+        .filter(|(tx, _)| tx.change && seen.insert(tx.txid.clone()))
+        .map(|(tx, _)| tx.txid.clone())
+        .collect::<Vec<_>>();
+
+    let outputs = rpc_client.z_viewtransactions(&txids)?
+        .into_iter()
+        .flat_map(|vtx| {
+            let txid = vtx.txid;
+            vtx.outputs.into_iter().map(move |out| (txid.clone(), out))
+        })
+        .filter(|(_, out)| out.outgoing && out.address.as_deref() == Some(addr))
+        .collect::<Vec<_>>();
+
+    let txids = outputs.iter().map(|(txid, _)| txid.clone()).collect::<Vec<_>>();
+    let wtxs = rpc_client.gettransactions(&txids)?;
+    let notes = outputs.into_iter()
+        .zip(wtxs)
+        .filter_map(|((txid, out), wtx)| inbox::Note::sent(&txid, out, wtx.time));
+    Ok(inbox::assemble(notes))
+}
+
+fn format_time(time: usize) -> String {
+    // Times out of chrono's range have no local date.
+    let dt = match Local.timestamp_opt(time as i64, 0).earliest() {
+        Some(dt) => dt,
+        None => return format!("{}s after the epoch", time),
+    };
+
+    let format_str = format!("%a %b %e{} %Y {} %T", ",", "at");
+    dt.format(&format_str).to_string()
+}
+
+/// Print messages along with their number in the store.
+fn report_messages(
+    heading: &str,
+    messages: &[(usize, &inbox::Message)],
+    tip: usize,
+    contacts: &contacts::Contacts,
+    term: &Term,
+) -> Result<(), Error> {
     let heading = format!(
-        "{:=<90}\n> Got {} messages.\n{:=<90}",
-        "", num_msg, "",
+        "{:=<90}\n> {}\n{:=<90}",
+        "", heading, "",
     );
-    let _ = term.write_line(&heading)?;
-    
-    txs.iter().enumerate().for_each(|(i, (tx, a))| {
-        let rpc::Tx{ txid, amount, memo, .. } = tx;
-        let wtx: rpc::WalletTx = rpc_client.gettransaction(txid).unwrap();
-        let dt: DateTime<Local> = Local.from_utc_datetime(
-            &NaiveDateTime::from_timestamp((wtx.time as u32).into(), 0)
-        );
+    term.write_line(&heading)?;
 
-        // let formatted_dt = dt.to_rfc3339();
-
-        let format_str = format!("%a %b %e{} %Y {} %T", ",", "at");
-        let formatted_dt = dt.format(&format_str);
+    for (i, msg) in messages {
+        let formatted_dt = format_time(msg.time);
 
         let line1 = format!(
-            "{:<2}Message #{} (val = {})\n",
-            "|", i, amount,
+            "{:<2}Message #{} (val = {}, txid = {})\n",
+            "|", i, msg.amount, msg.txid,
+        );
+        let line2 = &format!("{:<2}To: {}\n", "|", contacts.label(&msg.address));
+        let line3 = &format!(
+            "{:<2}Date: {}\n{:<2}Status: {}\n",
+            "|", formatted_dt, "|", msg.status(tip),
         );
-        let line2 = &format!("{:<2}To: {}\n", "|", a);
-        let line3 = &format!("{:<2}Date: {}\n", "|", formatted_dt);
-        let line4 = &format!("{:<2}\n", "|");
-        let line5 = &format!("{:<4}{}\n", "|", hex::hex_to_string(&memo).unwrap_or("".to_string()));
+        let line4 = &match msg.reply_to() {
+            Some(addr) => format!("{:<2}Reply-To: {}\n{:<2}\n", "|", contacts.label(addr), "|"),
+            None => format!("{:<2}\n", "|"),
+        };
+        let line5 = &format!("{:<4}{}\n", "|", msg.body);
         let end = &format!("{:=<90}", "");
         let block = line1 + line2 + line3 + line4 + line5 + end;
-        let _ = term.write_line(&block);
-    });
-    
+        term.write_line(&block)?;
+    }
+
+    Ok(())
+}
+
+/// Print a conversation as a chat transcript.
+fn report_conversation(
+    addr: &str,
+    entries: &[(Direction, inbox::Message)],
+    contacts: &contacts::Contacts,
+    term: &Term,
+) -> Result<(), Error> {
+    let heading = format!(
+        "{:=<90}\n> Conversation with {} ({} messages)\n{:=<90}",
+        "", contacts.label(addr), entries.len(), "",
+    );
+    term.write_line(&heading)?;
+
+    let them = contacts.name_of(addr).map_or_else(|| short_addr(addr), str::to_string);
+    for (i, (dir, msg)) in entries.iter().enumerate() {
+        if i > 0 {
+            term.write_line("|")?;
+        }
+        let who = match dir {
+            Direction::Sent => "me",
+            Direction::Received => them.as_str(),
+        };
+        term.write_line(&format!("{:<2}{} - {}:", "|", format_time(msg.time), who))?;
+        let parent = msg.envelope().and_then(|envelope| envelope.in_reply_to.as_deref());
+        if let Some(txid) = parent {
+            term.write_line(&format!("{:<4}(in reply to {})", "|", &txid[..txid.len().min(8)]))?;
+        }
+        for line in msg.body.to_string().lines() {
+            term.write_line(&format!("{:<4}{}", "|", line))?;
+        }
+    }
+    term.write_line(&format!("{:=<90}", ""))?;
+
+    Ok(())
+}
+
+/// Abbreviate a long address for display, keeping both ends.
+fn short_addr(addr: &str) -> String {
+    if addr.len() <= 24 || !addr.is_ascii() {
+        return addr.to_string();
+    }
+    format!("{}...{}", &addr[..12], &addr[addr.len() - 8..])
+}
+
+/// Print the opids of a sent message, and with `wait` the outcome of each.
+fn report_sent(
+    to: &[String],
+    opids: &[String],
+    wait: bool,
+    rpc_client: &rpc::ZClient,
+    contacts: &contacts::Contacts,
+    term: &Term,
+) -> Result<(), Error> {
+    let recipients = match to {
+        [addr] => contacts.label(addr),
+        _ => format!("{} recipients", to.len()),
+    };
+    let notify = match opids {
+        [opid] => format!("Message sent to {} with opid = {}", recipients, opid),
+        _ => format!(
+            "Message sent to {} in {} parts with opids = {}",
+            recipients, opids.len(), opids.join(", "),
+        ),
+    };
+    term.write_line(&notify)?;
+    if wait {
+        for opid in opids {
+            let op = wait_for_operation(rpc_client, opid, WAIT_TIMEOUT, term)?;
+            report_operation(&op, term)?;
+        }
+    }
+    Ok(())
+}
+
+/// Send the memos of a message to every recipient, returning one opid per
+/// transaction.
+///
+/// A message that fits in a memo goes out in a single transaction. A longer
+/// one is split into numbered parts. zcashd rejects a transaction paying
+/// the same address twice, so the parts can't be outputs of one
+/// transaction: part `i` for every recipient goes into transaction `i`,
+/// and only the first part carries `amount`. When a part fails, the error
+/// lists the opids of the parts already sent.
+fn send_msg_to(
+    c: &rpc::ZClient,
+    from: Option<String>,
+    to: &[String],
+    memos: &[hex::Memo],
+    amount: Option<Amount>,
+    opts: &rpc::SendOptions,
+) -> Result<Vec<String>, Error> {
+    let costs = send_costs(to.len(), memos.len(), amount.unwrap_or(DEFAULT_AMOUNT), opts)?;
+    let my_addr = funding_address(c, from, &costs, opts.min_confirmations())?;
+    let memos = memos.iter().map(hex::Memo::to_hex).collect::<Result<Vec<_>, _>>()?;
+
+    let mut opids = vec![];
+    let parts = memos.len();
+    for (i, memo) in memos.into_iter().enumerate() {
+        let value = match i {
+            0 => amount.unwrap_or(DEFAULT_AMOUNT),
+            _ => Amount::ZERO,
+        };
+        let outputs = to.iter()
+            .map(|addr| rpc::Output::new(addr, value, Some(memo.clone())))
+            .collect::<Vec<_>>();
+        match c.z_sendmany(&my_addr, &outputs, opts) {
+            Ok(opid) => opids.push(opid),
+            Err(e) if opids.is_empty() => return Err(e.into()),
+            Err(e) => return Err(Error::from(e).context(format!(
+                "Sent only {} of {} parts, with opids = {}",
+                opids.len(), parts, opids.join(", "),
+            ))),
+        }
+    }
+    Ok(opids)
+}
+
+/// What each transaction of a message sent in `parts` memos to
+/// `recipients` costs: `amount` to each recipient with the first part,
+/// plus the fee of every transaction.
+fn send_costs(
+    recipients: usize,
+    parts: usize,
+    amount: Amount,
+    opts: &rpc::SendOptions,
+) -> Result<Vec<Amount>, Error> {
+    let fee = opts.fee_for(recipients);
+    let first = amount.checked_mul(recipients as u64)
+        .and_then(|value| value.checked_add(fee))
+        .ok_or_else(|| anyhow!("Sending {} ZEC to {} recipients exceeds the ZEC supply", amount, recipients))?;
+    let mut costs = vec![first];
+    costs.resize(parts.max(1), fee);
+    costs.iter()
+        .try_fold(Amount::ZERO, |total, cost| total.checked_add(*cost))
+        .ok_or_else(|| anyhow!("Sending {} ZEC in {} parts exceeds the ZEC supply", amount, parts))?;
+    Ok(costs)
+}
+
+/// The address to send from: `from` when given, else the first of the
+/// wallet's z_addresses whose spendable notes pay for every transaction in
+/// `costs`. The change of one transaction can't fund the next until it
+/// has `minconf` confirmations, so each needs notes of its own.
+fn funding_address(
+    c: &rpc::ZClient,
+    from: Option<String>,
+    costs: &[Amount],
+    minconf: u32,
+) -> Result<String, Error> {
+    let cost = sum(costs)?;
+    let split = match costs.len() {
+        1 => String::new(),
+        n => format!(", in separate notes for each of its {} transactions", n),
+    };
+
+    if let Some(addr) = from {
+        // z_listunspent only knows shielded notes.
+        let amounts = match balance::Pool::of(&addr) {
+            Some(balance::Pool::Transparent) => vec![c.z_getbalance(&addr, minconf)?],
+            _ => spendable_notes(c, std::slice::from_ref(&addr), minconf)?
+                .into_iter()
+                .map(|(_, amount)| amount)
+                .collect(),
+        };
+        let balance = sum(&amounts)?;
+        if !balance::pays_separately(&amounts, costs) {
+            return Err(anyhow!(
+                "{} has {} ZEC spendable with {} confirmations, sending needs {} ZEC{}",
+                addr, balance, minconf, cost, split,
+            ));
+        }
+        return Ok(addr);
+    }
+
+    let total = c.z_gettotalbalance(minconf)?;
+    if total.private < cost {
+        return Err(anyhow!(
+            "The wallet has {} ZEC spendable in z_addresses with {} confirmations, sending needs {} ZEC",
+            total.private, minconf, cost,
+        ));
+    }
+    let addrs = wallet_addresses(c)?;
+    let notes = spendable_notes(c, &addrs, minconf)?;
+    let by_address = addrs.into_iter()
+        .map(|addr| {
+            let amounts = notes.iter()
+                .filter(|(owner, _)| *owner == addr)
+                .map(|(_, amount)| *amount)
+                .collect::<Vec<_>>();
+            (addr, amounts)
+        })
+        .collect::<Vec<_>>();
+    let largest = by_address.iter()
+        .map(|(_, amounts)| sum(amounts))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .max()
+        .unwrap_or(Amount::ZERO);
+    by_address.into_iter()
+        .find(|(_, amounts)| balance::pays_separately(amounts, costs))
+        .map(|(addr, _)| addr)
+        .ok_or_else(|| anyhow!(
+            "No single z_address holds the {} ZEC needed{}, the most any has is {} ZEC. \
+            Gather funds in one address, or pick one with --from",
+            cost, split, largest,
+        ))
+}
+
+/// Sum of wallet balances or of costs, which can't exceed the ZEC supply.
+fn sum(amounts: &[Amount]) -> Result<Amount, Error> {
+    amounts.iter()
+        .try_fold(Amount::ZERO, |total, amount| total.checked_add(*amount))
+        .ok_or_else(|| anyhow!("Amounts of {} notes or transactions add up past the ZEC supply", amounts.len()))
+}
+
+/// Spendable notes of `addrs` with `minconf` confirmations, with the
+/// address holding each.
+fn spendable_notes(
+    c: &rpc::ZClient,
+    addrs: &[String],
+    minconf: u32,
+) -> Result<Vec<(String, Amount)>, Error> {
+    Ok(c.z_listunspent(minconf, addrs)?
+        .into_iter()
+        .filter(|note| note.spendable)
+        .filter_map(|note| Some((note.address?, note.amount)))
+        .collect())
+}
+
+/// Create a receiving address of type `kind`. Unified addresses come from
+/// the wallet's first account, created if there is none yet.
+fn new_address(c: &rpc::ZClient, kind: rpc::AddressType) -> Result<String, Error> {
+    match kind {
+        rpc::AddressType::Sapling => Ok(c.z_getnewaddress()?),
+        rpc::AddressType::Unified => {
+            let accounts = c.z_listaccounts().map_err(|e| match e {
+                rpc::ZRpcError::MethodNotFound(_) => anyhow!("Unified addresses need zcashd 4.7 or later"),
+                e => e.into(),
+            })?;
+            let account = match accounts.first() {
+                Some(account) => account.account,
+                None => c.z_getnewaccount()?.account,
+            };
+            Ok(c.z_getaddressforaccount(account)?.address)
+        },
+    }
+}
+
+/// The wallet's z_addresses followed by the unified addresses of its
+/// accounts.
+fn wallet_addresses(c: &rpc::ZClient) -> Result<Vec<String>, Error> {
+    let mut addrs = c.z_listaddresses()?;
+    addrs.extend(account_addresses(c)?);
+    Ok(addrs)
+}
+
+/// Unified addresses derived for the wallet's accounts, which
+/// `z_listaddresses` leaves out.
+fn account_addresses(c: &rpc::ZClient) -> Result<Vec<String>, Error> {
+    // Wallets from before zcashd 4.7 have no accounts.
+    let accounts = match c.z_listaccounts() {
+        Ok(accounts) => accounts,
+        Err(rpc::ZRpcError::MethodNotFound(_)) => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    Ok(accounts.into_iter()
+        .flat_map(|account| account.addresses)
+        .map(|address| address.ua)
+        .collect())
+}
+
+/// The wallet's funds, counting notes with `minconf` confirmations as
+/// spendable and the others as pending.
+fn fetch_balances(c: &rpc::ZClient, minconf: u32) -> Result<balance::Balances, Error> {
+    // z_getbalance takes no unified address, their funds are counted by
+    // account below.
+    let addrs = c.z_listaddresses()?;
+    let confirmed = c.z_getbalances(&addrs, minconf)?;
+    let all = c.z_getbalances(&addrs, 0)?;
+    let addresses = addrs.into_iter()
+        .zip(confirmed.into_iter().zip(all))
+        .map(|(addr, (confirmed, all))| (addr, confirmed, all))
+        .collect();
+
+    // Wallets from before zcashd 4.7 have no accounts.
+    let accounts = match c.z_listaccounts() {
+        Ok(accounts) => accounts,
+        Err(rpc::ZRpcError::MethodNotFound(_)) => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    let accounts = accounts.into_iter()
+        .map(|account| {
+            let confirmed = c.z_getbalanceforaccount(account.account, minconf)?;
+            let all = c.z_getbalanceforaccount(account.account, 0)?;
+            Ok((account.account, confirmed, all))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let total = (c.z_gettotalbalance(minconf)?, c.z_gettotalbalance(0)?);
+    Ok(balance::Balances::new(addresses, accounts, total))
+}
+
+/// Print the wallet's funds by address, account and pool, and how many
+/// messages costing `cost` they pay for.
+fn report_balances(
+    balances: &balance::Balances,
+    minconf: u32,
+    cost: Amount,
+    contacts: &contacts::Contacts,
+    term: &Term,
+) -> Result<(), Error> {
+    let row = |name: &str, funds: &balance::Funds| format!(
+        "{:<2}{:<40}{:>20}{:>20}",
+        "|", name, funds.spendable.to_string(), funds.pending.to_string(),
+    );
+    let heading = |title: &str| format!(
+        "{:=<90}\n> {}\n{:=<90}\n{:<2}{:<40}{:>20}{:>20}",
+        "", title, "", "|", "", "Spendable", "Pending",
+    );
+
+    term.write_line(&heading("Addresses"))?;
+    for (addr, funds) in &balances.addresses {
+        let name = match contacts.name_of(addr) {
+            Some(name) => format!("{} ({})", name, short_addr(addr)),
+            None => short_addr(addr),
+        };
+        term.write_line(&row(&name, funds))?;
+    }
+    if !balances.accounts.is_empty() {
+        term.write_line(&heading("Accounts"))?;
+        for (account, pools) in &balances.accounts {
+            for (pool, funds) in pools {
+                term.write_line(&row(&format!("Account {} {}", account, pool), funds))?;
+            }
+        }
+    }
+    term.write_line(&heading("Pools"))?;
+    for (pool, funds) in &balances.pools {
+        term.write_line(&row(&pool.to_string(), funds))?;
+    }
+    term.write_line(&row("Total", &balances.total()))?;
+    term.write_line(&format!("{:=<90}", ""))?;
+
+    term.write_line(&format!(
+        "Spendable means {} or more confirmations. Enough for {} more messages of {} ZEC.",
+        minconf, balances.affords(cost), DEFAULT_AMOUNT,
+    ))?;
     Ok(())
 }
 
-fn send_msg_to(c: &rpc::ZClient, to: &str, msg: &str, amount: Option<f32>) -> Result<String, Error> {
-    let my_addr = c.z_listaddresses()?[0].clone();
-    let opid = c.z_sendmany(&my_addr, to, amount.unwrap_or(DEFAULT_AMOUNT), hex::str_to_hex(msg)?)?;
-    Ok(opid)
+/// Read recipient addresses from a file, one per line, skipping blank
+/// lines and `#` comments.
+fn read_recipients(path: &Path) -> Result<Vec<String>, Error> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recipients from {}", path.display()))?;
+    let addrs = contents.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    Ok(addrs)
 }
 
+/// Poll zcashd until the operation finishes, showing a spinner meanwhile.
+/// Fails once `timeout` has passed, leaving the operation to zcashd.
+fn wait_for_operation(
+    c: &rpc::ZClient,
+    opid: &str,
+    timeout: Duration,
+    term: &Term,
+) -> Result<rpc::Operation, Error> {
+    let opids = vec![opid.to_string()];
+    let started = std::time::Instant::now();
+    let mut tick = 0;
+    loop {
+        let op = c.z_getoperationstatus(&opids)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No operation with opid = {}", opid))?;
+        term.clear_line()?;
+        if op.is_finished() {
+            // Fetching the result also lets zcashd drop the finished operation.
+            let op = c.z_getoperationresult(&opids)?.into_iter().next().unwrap_or(op);
+            return Ok(op);
+        }
+        if started.elapsed() >= timeout {
+            return Err(anyhow!(
+                "Operation {} is still {} after {}s, look it up later with zmsg opstatus {}",
+                opid, op.status, timeout.as_secs(), opid,
+            ));
+        }
+        let progress = format!(
+            "{} Operation is {} ({}s)",
+            SPINNER[tick % SPINNER.len()], op.status, started.elapsed().as_secs(),
+        );
+        term.write_str(&progress)?;
+        thread::sleep(POLL_INTERVAL);
+        tick += 1;
+    }
+}
+
+/// Print the txid of a successful operation, or fail with zcashd's reason.
+fn report_operation(op: &rpc::Operation, term: &Term) -> Result<(), Error> {
+    match (&op.result, &op.error) {
+        (Some(res), _) => {
+            term.write_line(&format!("Transaction broadcast with txid = {}", res.txid))?;
+            Ok(())
+        },
+        (None, Some(err)) => Err(anyhow!("Operation {} {}: {}", op.id, op.status, err.message)),
+        (None, None) => Err(anyhow!("Operation {} {}", op.id, op.status)),
+    }
+}
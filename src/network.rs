@@ -0,0 +1,146 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{anyhow, Error};
+
+/// The zcash network a node runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Main,
+    Test,
+    Regtest,
+}
+
+impl Network {
+    pub const ALL: [Network; 3] = [Network::Main, Network::Test, Network::Regtest];
+
+    /// RPC port zcashd listens on when `rpcport` is not set.
+    pub fn default_rpc_port(self) -> u16 {
+        match self {
+            Self::Main => 8232,
+            Self::Test | Self::Regtest => 18232,
+        }
+    }
+
+    /// Subdirectory of the datadir holding this network's chain state.
+    pub fn datadir_subdir(self) -> &'static str {
+        match self {
+            Self::Main => "",
+            Self::Test => "testnet3",
+            Self::Regtest => "regtest",
+        }
+    }
+
+    /// Human-readable prefixes of addresses on this network: Sapling,
+    /// unified, transparent P2PKH/P2SH and Sprout.
+    pub fn address_prefixes(self) -> &'static [&'static str] {
+        match self {
+            Self::Main => &["zs1", "u1", "t1", "t3", "zc"],
+            Self::Test => &["ztestsapling1", "utest1", "tm", "t2", "zt"],
+            Self::Regtest => &["zregtestsapling1", "uregtest1", "tm", "t2", "zt"],
+        }
+    }
+
+    /// Whether `addr` has the prefix of an address on this network.
+    ///
+    /// Prefixes overlap between networks (the testnet Sapling prefix
+    /// starts with the Sprout prefix shared by testnet and regtest), so
+    /// only the longest matching prefix counts.
+    pub fn accepts(self, addr: &str) -> bool {
+        let longest = |net: Network| {
+            net.address_prefixes()
+                .iter()
+                .filter(|prefix| addr.starts_with(*prefix))
+                .map(|prefix| prefix.len())
+                .max()
+        };
+        match longest(self) {
+            Some(len) => Self::ALL.iter().all(|net| longest(*net).unwrap_or(0) <= len),
+            None => false,
+        }
+    }
+
+    /// Fail unless `addr` is an address of this network.
+    pub fn validate_address(self, addr: &str) -> Result<(), Error> {
+        if self.accepts(addr) {
+            return Ok(());
+        }
+        match Self::ALL.iter().find(|net| net.accepts(addr)) {
+            Some(other) => Err(anyhow!(
+                "{} is a {} address but zmsg is using {}", addr, other, self,
+            )),
+            None => Err(anyhow!("{} is not a valid {} address", addr, self)),
+        }
+    }
+
+    /// Fail unless `addr` is a shielded address of this network. zcashd
+    /// rejects a memo sent to a transparent address.
+    pub fn validate_recipient(self, addr: &str) -> Result<(), Error> {
+        self.validate_address(addr)?;
+        if self.address_prefixes()[2..4].iter().any(|prefix| addr.starts_with(prefix)) {
+            return Err(anyhow!("{} is a transparent address, it can't receive messages", addr));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => write!(f, "mainnet"),
+            Self::Test => write!(f, "testnet"),
+            Self::Regtest => write!(f, "regtest"),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" | "mainnet" => Ok(Self::Main),
+            "test" | "testnet" => Ok(Self::Test),
+            "regtest" => Ok(Self::Regtest),
+            _ => Err(anyhow!("Unknown network {}, expected main, test or regtest", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_address() {
+        let sapling_test = "ztestsapling13vnh6svpvtpgntphha7nsafchx7zhhzu0gnptkztvahtvyueujwha2gcf976vt609qd8xjkaqwf";
+        let sapling_main = "zs1z7rejlpsa98s2rrrfkwmaxu53e4ue0ulcrw0h4x5g8jl04tak0d3mm47vdtahatqrlkngh9sly";
+
+        assert!(Network::Test.validate_address(sapling_test).is_ok());
+        assert!(Network::Main.validate_address(sapling_main).is_ok());
+        assert!(Network::Regtest.validate_address("uregtest1qqqq").is_ok());
+        assert!(Network::Test.validate_address("tmYXBYJj1K7vhejSec5osXK2QsGa5MTisUQ").is_ok());
+
+        let err = Network::Main.validate_address(sapling_test).unwrap_err();
+        assert!(err.to_string().contains("is a testnet address"));
+        assert!(Network::Main.validate_address("utest1qqqq").is_err());
+        assert!(Network::Regtest.validate_address(sapling_test).is_err());
+        assert!(Network::Regtest.validate_address("ztSproutRegtestOrTestnet").is_ok());
+        assert!(Network::Test.validate_address("hello").is_err());
+
+        assert!(Network::Test.validate_recipient(sapling_test).is_ok());
+        assert!(Network::Regtest.validate_recipient("uregtest1qqqq").is_ok());
+        let err = Network::Test.validate_recipient("tmYXBYJj1K7vhejSec5osXK2QsGa5MTisUQ").unwrap_err();
+        assert!(err.to_string().contains("transparent"));
+        assert!(Network::Main.validate_recipient(sapling_test).is_err());
+    }
+
+    #[test]
+    fn test_network_defaults() {
+        assert!("main".parse::<Network>().unwrap() == Network::Main);
+        assert!("regtest".parse::<Network>().unwrap() == Network::Regtest);
+        assert!("moon".parse::<Network>().is_err());
+        assert!(Network::Main.default_rpc_port() == 8232);
+        assert!(Network::Test.default_rpc_port() == 18232);
+        assert!(Network::Test.datadir_subdir() == "testnet3");
+    }
+}
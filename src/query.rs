@@ -0,0 +1,233 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{anyhow, Error};
+use regex::Regex;
+use crate::amount::Amount;
+use crate::hex::Memo;
+use crate::inbox::{Body, Message};
+use crate::store::Since;
+
+/// What messages can be ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Time,
+    Amount,
+    Height,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 3] = [SortKey::Time, SortKey::Amount, SortKey::Height];
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortKey::Time => write!(f, "time"),
+            SortKey::Amount => write!(f, "amount"),
+            SortKey::Height => write!(f, "height"),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SortKey::ALL.iter()
+            .find(|key| key.to_string() == s)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown sort key {}, expected one of time, amount or height", s))
+    }
+}
+
+/// Which messages to list, in what order, and which page of them.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Our address the message was received on.
+    pub address: Option<String>,
+    /// Only messages from this height or time on.
+    pub after: Option<Since>,
+    /// Only messages before this height or time.
+    pub before: Option<Since>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    pub min_confirmations: Option<usize>,
+    /// Text the message must contain.
+    pub text: Option<String>,
+    /// Pattern the text of the message must match.
+    pub pattern: Option<Regex>,
+    /// Leave out messages with an empty memo.
+    pub has_memo: bool,
+    /// Order to list messages in, instead of the order they were first seen.
+    pub sort: Option<SortKey>,
+    pub reverse: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Whether `msg` passes every filter, the chain tip being at `tip`.
+    // `Option::is_none_or` would need Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn matches(&self, msg: &Message, tip: usize) -> bool {
+        // Messages not mined yet are the newest there are.
+        let height = msg.height.unwrap_or(usize::MAX);
+        let text = text_of(msg);
+
+        self.address.as_ref().map_or(true, |addr| msg.address == *addr)
+            && self.after.map_or(true, |after| after.includes(height, msg.time))
+            && self.before.map_or(true, |before| !before.includes(height, msg.time))
+            && self.min_amount.map_or(true, |min| msg.amount >= min)
+            && self.max_amount.map_or(true, |max| msg.amount <= max)
+            && self.min_confirmations.map_or(true, |min| msg.confirmations(tip).unwrap_or(0) >= min)
+            && self.text.as_ref().map_or(true, |s| text.as_deref().is_some_and(|t| t.contains(s.as_str())))
+            && self.pattern.as_ref().map_or(true, |re| text.as_deref().is_some_and(|t| re.is_match(t)))
+            && (!self.has_memo || has_memo(msg))
+    }
+
+    /// Whether anything was asked for beyond the default listing, which
+    /// makes the query a search of every stored message.
+    pub fn is_search(&self) -> bool {
+        self.address.is_some()
+            || self.after.is_some()
+            || self.before.is_some()
+            || self.min_amount.is_some()
+            || self.max_amount.is_some()
+            || self.min_confirmations.is_some()
+            || self.text.is_some()
+            || self.pattern.is_some()
+            || self.has_memo
+            || self.sort.is_some()
+            || self.reverse
+            || self.offset > 0
+            || self.limit.is_some()
+    }
+
+    /// Filter, sort and page numbered messages.
+    pub fn apply<'a>(
+        &self,
+        messages: Vec<(usize, &'a Message)>,
+        tip: usize,
+    ) -> Vec<(usize, &'a Message)> {
+        let mut messages = messages.into_iter()
+            .filter(|(_, msg)| self.matches(msg, tip))
+            .collect::<Vec<_>>();
+        // Sorting is stable, so ties stay in the order they were seen.
+        match self.sort {
+            Some(SortKey::Time) => messages.sort_by_key(|(_, msg)| msg.time),
+            Some(SortKey::Amount) => messages.sort_by_key(|(_, msg)| msg.amount),
+            Some(SortKey::Height) => messages.sort_by_key(|(_, msg)| msg.height.unwrap_or(usize::MAX)),
+            None => {},
+        }
+        if self.reverse {
+            messages.reverse();
+        }
+        messages.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// The text of a message, if it is not binary or incomplete.
+fn text_of(msg: &Message) -> Option<String> {
+    match &msg.body {
+        Body::Memo(Memo::Text(text)) => Some(text.clone()),
+        Body::Envelope(envelope) => envelope.text(),
+        _ => None,
+    }
+}
+
+fn has_memo(msg: &Message) -> bool {
+    match &msg.body {
+        Body::Memo(Memo::Empty) | Body::Incomplete { .. } => false,
+        Body::Memo(Memo::Text(text)) => !text.is_empty(),
+        Body::Envelope(envelope) => !envelope.body.is_empty(),
+        Body::Memo(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Envelope;
+
+    fn message(txid: &str, zat: u64, height: usize, body: Body) -> Message {
+        Message {
+            txid: txid.to_string(),
+            outindex: 0,
+            address: if zat < 50_000 { "zs1small" } else { "zs1big" }.to_string(),
+            amount: Amount::from_zat(zat),
+            time: height * 75,
+            height: Some(height),
+            msg_id: None,
+            body,
+        }
+    }
+
+    fn text(s: &str) -> Body {
+        Body::Memo(Memo::Text(s.to_string()))
+    }
+
+    fn txids(messages: &[(usize, &Message)]) -> Vec<String> {
+        messages.iter().map(|(_, msg)| msg.txid.clone()).collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        let messages = [
+            message("tx0", 10_000, 100, text("invoice #1234 paid")),
+            message("tx1", 90_000, 200, Body::Envelope(Envelope::new("Invoice #99 refund"))),
+            message("tx2", 20_000, 300, Body::Memo(Memo::Arbitrary(vec![1, 2]))),
+            message("tx3", 70_000, 400, text("")),
+        ];
+        let numbered = messages.iter().enumerate().collect::<Vec<_>>();
+        let run = |query: Query| txids(&query.apply(numbered.clone(), 400));
+
+        assert!(run(Query::default()) == ["tx0", "tx1", "tx2", "tx3"]);
+        assert!(!Query::default().is_search());
+        assert!(Query { has_memo: true, ..Query::default() }.is_search());
+        assert!(run(Query { address: Some("zs1big".to_string()), ..Query::default() }) == ["tx1", "tx3"]);
+        assert!(run(Query {
+            after: Some(Since::Height(200)),
+            before: Some(Since::Time(400 * 75)),
+            ..Query::default()
+        }) == ["tx1", "tx2"]);
+        assert!(run(Query {
+            min_amount: Some(Amount::from_zat(20_000)),
+            max_amount: Some(Amount::from_zat(80_000)),
+            ..Query::default()
+        }) == ["tx2", "tx3"]);
+        assert!(run(Query { min_confirmations: Some(102), ..Query::default() }) == ["tx0", "tx1"]);
+        assert!(run(Query { text: Some("invoice".to_string()), ..Query::default() }) == ["tx0"]);
+        assert!(run(Query { pattern: Some(Regex::new(r"(?i)invoice #\d+").unwrap()), ..Query::default() }) == ["tx0", "tx1"]);
+        assert!(run(Query { has_memo: true, ..Query::default() }) == ["tx0", "tx1", "tx2"]);
+    }
+
+    #[test]
+    fn test_sort_and_page() {
+        let messages = [
+            message("tx0", 30_000, 300, text("a")),
+            message("tx1", 10_000, 100, text("b")),
+            message("tx2", 20_000, 200, text("c")),
+        ];
+        let numbered = messages.iter().enumerate().collect::<Vec<_>>();
+
+        let query = Query { sort: Some(SortKey::Amount), ..Query::default() };
+        let sorted = query.apply(numbered.clone(), 300);
+        assert!(txids(&sorted) == ["tx1", "tx2", "tx0"]);
+        // Messages keep their numbers whatever the order.
+        assert!(sorted[0].0 == 1);
+
+        let query = Query {
+            sort: Some(SortKey::Height),
+            reverse: true,
+            offset: 1,
+            limit: Some(1),
+            ..Query::default()
+        };
+        assert!(query.is_search());
+        assert!(txids(&query.apply(numbered, 300)) == ["tx2"]);
+        assert!("size".parse::<SortKey>().is_err());
+    }
+}
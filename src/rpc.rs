@@ -1,20 +1,155 @@
-use std::str;
-use anyhow::Error;
+use std::{fmt, str};
+use std::convert::TryFrom;
+use std::str::FromStr;
 use reqwest::blocking::Client;
 use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use url::{Url, ParseError};
 use serde_json::{self, json};
+use crate::amount::{Amount, SignedAmount, MAX_MONEY};
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::AsyncZClient;
+
+/* zcashd JSON-RPC error codes (see src/rpc/protocol.h in zcashd) */
+const RPC_METHOD_NOT_FOUND: i64 = -32601;
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+const RPC_WALLET_INSUFFICIENT_FUNDS: i64 = -6;
+const RPC_WALLET_UNLOCK_NEEDED: i64 = -13;
+const RPC_IN_WARMUP: i64 = -28;
+
+/// zcashd's `minconf` default for `z_sendmany`, sent when a later
+/// positional argument has to be given.
+const DEFAULT_SEND_MINCONF: u32 = 10;
+/// zcashd's `maxconf` default for `z_listunspent`, sent so the address
+/// filter can follow it.
+const UNSPENT_MAXCONF: u32 = 9_999_999;
+
+/* ZIP-317 conventional fee: a marginal fee per logical action, with a
+ * floor of two actions */
+const MARGINAL_FEE: Amount = Amount::from_zat(5_000);
+const GRACE_ACTIONS: usize = 2;
+
+const DEFAULT_URL: &str = "http://127.0.0.1:9999";
+
+pub type ZResult<T> = Result<T, ZRpcError>;
+
+/// Errors returned by every `ZClient` call.
+#[derive(Debug)]
+pub enum ZRpcError {
+    /// The request never got a response (connection refused, timeout, ...).
+    Transport(reqwest::Error),
+    /// zcashd answered with a non-success HTTP status and no JSON-RPC error.
+    Http { status: u16, body: String },
+    /// zcashd rejected the RPC credentials.
+    Auth,
+    /// The wallet does not hold enough funds (code -6).
+    InsufficientFunds(String),
+    /// The address or key is malformed or unknown (code -5).
+    InvalidAddress(String),
+    /// The wallet is encrypted and must be unlocked first (code -13).
+    WalletLocked(String),
+    /// zcashd does not know the RPC method (code -32601).
+    MethodNotFound(String),
+    /// Any other error reported by zcashd.
+    Rpc { code: i64, message: String },
+    /// The response body could not be decoded.
+    Decode(serde_json::Error),
+    /// A batch response had no entry for the request with this id.
+    MissingResponse(i32),
+}
+
+impl ZRpcError {
+    /// Map a zcashd `{"code", "message"}` error object to a variant.
+    pub fn from_code(code: i64, message: String) -> Self {
+        match code {
+            RPC_WALLET_INSUFFICIENT_FUNDS => Self::InsufficientFunds(message),
+            RPC_INVALID_ADDRESS_OR_KEY => Self::InvalidAddress(message),
+            RPC_WALLET_UNLOCK_NEEDED => Self::WalletLocked(message),
+            RPC_METHOD_NOT_FOUND => Self::MethodNotFound(message),
+            _ => Self::Rpc{ code, message },
+        }
+    }
+
+    /// The zcashd error code, if the error came from zcashd itself.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Self::InsufficientFunds(_) => Some(RPC_WALLET_INSUFFICIENT_FUNDS),
+            Self::InvalidAddress(_) => Some(RPC_INVALID_ADDRESS_OR_KEY),
+            Self::WalletLocked(_) => Some(RPC_WALLET_UNLOCK_NEEDED),
+            Self::MethodNotFound(_) => Some(RPC_METHOD_NOT_FOUND),
+            Self::Rpc{ code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Whether the same call may succeed later: zcashd is down, starting
+    /// up, or too busy to answer.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(_) => true,
+            Self::Http{ status, .. } => *status >= 500,
+            Self::Rpc{ code, .. } => *code == RPC_IN_WARMUP,
+            _ => false,
+        }
+    }
+
+    /// Whether a client with freshly read credentials may get through:
+    /// zcashd writes a new cookie each time it starts.
+    pub fn needs_reconnect(&self) -> bool {
+        matches!(self, Self::Auth)
+    }
+}
+
+impl fmt::Display for ZRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "Failed to reach zcashd: {}", e),
+            Self::Http{ status, body } => write!(f, "zcashd returned HTTP {}: {}", status, body),
+            Self::Auth => write!(f, "zcashd rejected the RPC credentials"),
+            Self::InsufficientFunds(m) => write!(f, "Insufficient funds: {}", m),
+            Self::InvalidAddress(m) => write!(f, "Invalid address: {}", m),
+            Self::WalletLocked(m) => write!(f, "Wallet is locked: {}", m),
+            Self::MethodNotFound(m) => write!(f, "Method not found: {}", m),
+            Self::Rpc{ code, message } => write!(f, "zcashd error {}: {}", code, message),
+            Self::Decode(e) => write!(f, "Failed to decode zcashd response: {}", e),
+            Self::MissingResponse(id) => write!(f, "zcashd sent no response for batch request {}", id),
+        }
+    }
+}
+
+impl std::error::Error for ZRpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            Self::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ZRpcError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e)
+    }
+}
 
 
 pub struct ZClientBuilder {
-    client: ZClient,
+    url: String,
+    user: String,
+    password: Option<String>,
 }
 
 impl Default for ZClientBuilder {
     fn default() -> Self {
         ZClientBuilder {
-            client: ZClient::default(),
+            url: DEFAULT_URL.to_string(),
+            user: String::new(),
+            password: None,
         }
     }
 }
@@ -22,26 +157,85 @@ impl Default for ZClientBuilder {
 impl ZClientBuilder {
     pub fn with_url(mut self, url: String) -> Result<Self, ParseError> {
         let _ = Url::parse(&url)?;
-        self.client.url = url;
+        self.url = url;
         Ok(self)
     }
 
     pub fn with_auth(mut self, user: String, password: Option<String>) -> Self {
-        self.client.user = user;
-        self.client.password = password;
+        self.user = user;
+        self.password = password;
         self
     }
 
     pub fn build(self) -> ZClient {
-        self.client
+        ZClient {
+            c: Client::default(),
+            url: self.url,
+            user: self.user,
+            password: self.password,
+        }
+    }
+
+    /// Build an async client with the same URL and credentials.
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> AsyncZClient {
+        AsyncZClient::new(self.url, self.user, self.password)
     }
 }
 
+/// The `error` member of a JSON-RPC response.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ZErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ZResponse<T> {
-    result: T,
-    error: Option<String>,
-    id: Option<i32>,
+    result: Option<T>,
+    error: Option<ZErrorObject>,
+    pub id: Option<i32>,
+}
+
+impl<T: DeserializeOwned> ZResponse<T> {
+    /// Decode a raw HTTP response body, taking the status into account.
+    ///
+    /// zcashd replies to failed calls with HTTP 500 and a JSON-RPC error
+    /// body, so the body is always inspected before the status.
+    pub fn from_body(status: StatusCode, body: &str) -> ZResult<T> {
+        parse_body::<ZResponse<T>>(status, body)?.into_result()
+    }
+
+    /// Decode the body of a batch call made of `len` requests with ids
+    /// `0..len`, returning one result per request in request order.
+    pub fn batch_from_body(status: StatusCode, body: &str, len: usize) -> ZResult<Vec<ZResult<T>>> {
+        let mut slots = (0..len).map(|_| None).collect::<Vec<Option<ZResult<T>>>>();
+        for res in parse_body::<Vec<ZResponse<T>>>(status, body)? {
+            let slot = res.id
+                .and_then(|id| usize::try_from(id).ok())
+                .and_then(|id| slots.get_mut(id));
+            if let Some(slot) = slot {
+                *slot = Some(res.into_result());
+            }
+        }
+        let results = slots.into_iter()
+            .enumerate()
+            .map(|(id, slot)| slot.unwrap_or(Err(ZRpcError::MissingResponse(id as i32))))
+            .collect::<Vec<_>>();
+        Ok(results)
+    }
+
+    /// Turn the response into its result or the error reported by zcashd.
+    pub fn into_result(self) -> ZResult<T> {
+        if let Some(ZErrorObject{ code, message }) = self.error {
+            return Err(ZRpcError::from_code(code, message));
+        }
+        match self.result {
+            Some(result) => Ok(result),
+            // Give result types such as `Option<_>` a chance to accept null.
+            None => serde_json::from_value(serde_json::Value::Null).map_err(ZRpcError::Decode),
+        }
+    }
 }
 
 // FIXME: Manually implement PartialEq for testing.
@@ -49,14 +243,19 @@ pub struct ZResponse<T> {
 #[serde(rename_all = "camelCase")]
 pub struct Tx {
     pub txid: String,
-    pub amount: f32,
+    pub amount: Amount,
     pub memo: String,
-    amount_zat: usize,
-    outindex: usize,
-    confirmations: usize,
-    blockheight: usize,
-    blockindex: usize,
-    blocktime: usize,
+    pub amount_zat: u64,
+    pub outindex: usize,
+    pub confirmations: usize,
+    /// Height, index and time of the block holding the transaction, which
+    /// zcashd leaves out while it is in the mempool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockheight: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockindex: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocktime: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     jsindex: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -64,6 +263,22 @@ pub struct Tx {
     pub change: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// A note the wallet has not spent yet.
+/// Result from "z_listunspent" method.
+pub struct UnspentNote {
+    pub txid: String,
+    pub confirmations: usize,
+    /// Whether the wallet holds the spending key, not only a viewing key.
+    pub spendable: bool,
+    /// Address the note was received on, when zcashd knows it.
+    #[serde(default)]
+    pub address: Option<String>,
+    pub amount: Amount,
+    #[serde(default)]
+    pub change: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Detailed information abut in-wallet transaction <txid>.
@@ -71,12 +286,17 @@ pub struct Tx {
 pub struct WalletTx {
     pub status: String,
     pub txid: String,
-    pub amount: f32,
-    amount_zat: usize,
+    pub amount: SignedAmount,
+    pub amount_zat: i64,
     confirmations: usize,
-    blockhash: String,
-    blockindex: usize,
-    blocktime: usize,
+    /// Block holding the transaction, which zcashd leaves out while it is
+    /// in the mempool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockhash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockindex: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocktime: Option<usize>,
     pub time: usize,
     pub timereceived: usize,
     hex: String,
@@ -84,17 +304,18 @@ pub struct WalletTx {
     vjoinsplit: Vec<serde_json::Value>,
 }
 
+#[cfg(test)]
 impl WalletTx {
     fn mock() -> Self {
         WalletTx{
             status: "mined".to_owned(),
             txid: "foobarbaz".to_owned(),
-            amount: 99.999,
-            amount_zat: 99999999,
+            amount: SignedAmount::from_zat(9999900000),
+            amount_zat: 9999900000,
             confirmations: 9,
-            blockhash: "9999999999999999999999".to_owned(),
-            blockindex: 99,
-            blocktime: 99999999,
+            blockhash: Some("9999999999999999999999".to_owned()),
+            blockindex: Some(99),
+            blocktime: Some(99999999),
             time: 99999999,
             timereceived: 99999999,
             hex: "999999999999999999999999999999999999999999999999999".to_owned(),
@@ -121,11 +342,309 @@ impl WalletTx {
     }
 }
 
+/// `gettransaction` result for a transaction still in the mempool.
+#[cfg(test)]
+fn pending_wtx() -> serde_json::Value {
+    json!({
+        "status": "waiting",
+        "txid": "mempooltx",
+        "amount": 0.0001,
+        "amountZat": 10000,
+        "confirmations": 0,
+        "time": 1700000000,
+        "timereceived": 1700000000,
+        "hex": "00",
+        "details": [],
+        "vjoinsplit": [],
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A shielded output of a wallet transaction, decrypted by the wallet.
+pub struct ViewOutput {
+    /// Shielded pool of the output: "sprout", "sapling" or "orchard".
+    #[serde(rename = "type")]
+    pub pool: String,
+    /// Recipient, when the wallet can tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Index of the output in its pool; Orchard outputs are numbered as
+    /// actions instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<usize>,
+    /// Whether the wallet sent this output to someone else.
+    pub outgoing: bool,
+    #[serde(default)]
+    pub wallet_internal: bool,
+    pub value: Amount,
+    pub value_zat: u64,
+    pub memo: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Shielded spends and outputs of a wallet transaction.
+/// Result from "z_viewtransaction" method.
+pub struct ViewTx {
+    pub txid: String,
+    pub spends: Vec<serde_json::Value>,
+    pub outputs: Vec<ViewOutput>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// Lifecycle of an async zcashd operation such as `z_sendmany`.
+pub enum OperationState {
+    Queued,
+    Executing,
+    Success,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Result payload of a successful operation.
+pub struct OperationResult {
+    pub txid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Wallet balances by kind of address.
+/// Result from "z_gettotalbalance" method.
+pub struct TotalBalance {
+    pub transparent: Amount,
+    pub private: Amount,
+    pub total: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// A unified address derived for an account.
+pub struct AccountAddress {
+    pub ua: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// A ZIP-32 account of the wallet.
+/// Result from "z_listaccounts" method.
+pub struct Account {
+    pub account: u32,
+    #[serde(default)]
+    pub addresses: Vec<AccountAddress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// An account just created.
+/// Result from "z_getnewaccount" method.
+pub struct NewAccount {
+    pub account: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// A fresh unified address of an account.
+/// Result from "z_getaddressforaccount" method.
+pub struct UnifiedAddress {
+    pub account: u32,
+    pub address: String,
+    /// Pools the address can receive into, e.g. `sapling` and `orchard`.
+    #[serde(default)]
+    pub receiver_types: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolBalance {
+    pub value_zat: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+/// Balance of an account in each pool; pools it has no funds in are left
+/// out.
+pub struct PoolBalances {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transparent: Option<PoolBalance>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sapling: Option<PoolBalance>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orchard: Option<PoolBalance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Result from "z_getbalanceforaccount" method.
+pub struct AccountBalance {
+    pub pools: PoolBalances,
+    pub minimum_confirmations: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Status of an async operation.
+/// Result from "z_getoperationstatus" and "z_getoperationresult" methods.
+pub struct Operation {
+    pub id: String,
+    pub status: OperationState,
+    pub creation_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<OperationResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ZErrorObject>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_secs: Option<f64>,
+}
+
+impl fmt::Display for OperationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Queued => write!(f, "queued"),
+            Self::Executing => write!(f, "executing"),
+            Self::Success => write!(f, "success"),
+            Self::Failed => write!(f, "failed"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl Operation {
+    /// Whether the operation has stopped, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.status, OperationState::Queued | OperationState::Executing)
+    }
+}
+
+/// How much information a `z_sendmany` transaction may reveal.
+/// Serialized to the names zcashd uses for its `privacyPolicy` argument.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum PrivacyPolicy {
+    FullPrivacy,
+    AllowRevealedAmounts,
+    AllowRevealedRecipients,
+    AllowRevealedSenders,
+    AllowFullyTransparent,
+    AllowLinkingAccountAddresses,
+    NoPrivacy,
+    LegacyCompat,
+}
+
+impl PrivacyPolicy {
+    pub const ALL: [PrivacyPolicy; 8] = [
+        Self::FullPrivacy,
+        Self::AllowRevealedAmounts,
+        Self::AllowRevealedRecipients,
+        Self::AllowRevealedSenders,
+        Self::AllowFullyTransparent,
+        Self::AllowLinkingAccountAddresses,
+        Self::NoPrivacy,
+        Self::LegacyCompat,
+    ];
+}
+
+impl fmt::Display for PrivacyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for PrivacyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter()
+            .find(|policy| policy.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                format!("Unknown privacy policy {}, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Kind of shielded address to create.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddressType {
+    /// A legacy Sapling address, from `z_getnewaddress`.
+    Sapling,
+    /// A unified address of a ZIP-32 account, from `z_getaddressforaccount`.
+    Unified,
+}
+
+impl AddressType {
+    pub const ALL: [AddressType; 2] = [Self::Sapling, Self::Unified];
+}
+
+impl fmt::Display for AddressType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sapling => write!(f, "sapling"),
+            Self::Unified => write!(f, "unified"),
+        }
+    }
+}
+
+impl FromStr for AddressType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("Unknown address type {}, expected sapling or unified", s))
+    }
+}
+
+/// One output of a `z_sendmany` transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Output {
+    pub address: String,
+    pub amount: Amount,
+    /// Hex-encoded memo, only allowed for shielded recipients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl Output {
+    pub fn new(address: &str, amount: Amount, memo: Option<String>) -> Self {
+        Output{
+            address: address.to_string(),
+            amount,
+            memo,
+        }
+    }
+}
+
+/// Optional `z_sendmany` arguments. The defaults let zcashd choose: 10
+/// confirmations, the ZIP-317 conventional fee and its default policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SendOptions {
+    pub minconf: Option<u32>,
+    pub fee: Option<Amount>,
+    pub privacy_policy: Option<PrivacyPolicy>,
+}
+
+impl SendOptions {
+    /// Confirmations a note needs before zcashd spends it.
+    pub fn min_confirmations(&self) -> u32 {
+        self.minconf.unwrap_or(DEFAULT_SEND_MINCONF)
+    }
+
+    /// Fee of a transaction paying `outputs` shielded recipients from one
+    /// shielded address: the one set, or else the ZIP-317 conventional fee
+    /// counting change as an output. Spending many small notes can cost
+    /// more, so this is an estimate.
+    pub fn fee_for(&self, outputs: usize) -> Amount {
+        self.fee.unwrap_or_else(|| {
+            let actions = (outputs + 1).max(GRACE_ACTIONS) as u64;
+            MARGINAL_FEE.checked_mul(actions).unwrap_or(Amount::from_zat(MAX_MONEY))
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ZRequest<T> {
     jsonrpc: String,
     method: String,
     params: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i32>,
 }
 
 pub struct ZRequestBuilder<T> {
@@ -156,6 +675,11 @@ impl<T> ZRequestBuilder<T> {
         self
     }
 
+    pub fn id(mut self, id: i32) -> Self {
+        self.request.id = Some(id);
+        self
+    }
+
     pub fn build(self) -> ZRequest<T> {
         self.request
     }
@@ -167,6 +691,7 @@ impl<T> Default for ZRequest<T> {
             jsonrpc: "1.0".to_string(),
             method: "getbalance".to_string(),
             params: Vec::<T>::new(),
+            id: None,
         }
     }
 }
@@ -186,12 +711,7 @@ pub struct ZClient {
 
 impl Default for ZClient {
     fn default() -> Self {
-        Self {
-            c: Client::default(),
-            url: "http://127.0.0.1:9999".to_string(),
-            user: String::new(),
-            password: None,
-        }
+        ZClientBuilder::default().build()
     }
 }
 
@@ -200,167 +720,685 @@ impl ZClient {
         ZClientBuilder::default()
     }
 
-    fn send<S, T>(&self, req: ZRequest<S>) -> Result<ZResponse<T>, Error> 
+    fn send<S, T>(&self, req: ZRequest<S>) -> ZResult<T>
     where S: Serialize + 'static, T: DeserializeOwned + 'static {
         let res = self.c.post(self.url.clone())
             .basic_auth(self.user.clone(), self.password.clone())
             .header(CONTENT_TYPE, "text/octet-stream")
             .body(json!(req).to_string())
-            .send()?
-            .json::<ZResponse<T>>()?;
-        Ok(res)
+            .send()?;
+        let status = res.status();
+        let body = res.text()?;
+        ZResponse::<T>::from_body(status, &body)
+    }
+
+    /// Send several requests in a single POST. Results are returned in
+    /// request order; each one fails or succeeds on its own.
+    pub fn batch<S, T>(&self, reqs: Vec<ZRequest<S>>) -> ZResult<Vec<ZResult<T>>>
+    where S: Serialize + 'static, T: DeserializeOwned + 'static {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        let len = reqs.len();
+        let res = self.c.post(self.url.clone())
+            .basic_auth(self.user.clone(), self.password.clone())
+            .header(CONTENT_TYPE, "text/octet-stream")
+            .body(json!(number_batch(reqs)).to_string())
+            .send()?;
+        let status = res.status();
+        let body = res.text()?;
+        ZResponse::<T>::batch_from_body(status, &body, len)
     }
     
     /* Zcash RPC API implementation */
 
-    pub fn getbalance(&self) -> Result<f32, Error> {
-        let req = ZRequest::<String>::builder()
-            .method("getbalance".to_string())
-            .build();
-            
-        let res: ZResponse<f32> = self.send::<String, f32>(req)?;
-        Ok(res.result)
+    pub fn getbalance(&self) -> ZResult<Amount> {
+        self.send::<String, Amount>(ZRequest::getbalance())
     }
 
-    pub fn z_listaddresses(&self) -> Result<Vec<String>, Error> {
-        let req = ZRequest::<String>::builder()
-            .method("z_listaddresses".to_string())
-            .build();
-        let res: ZResponse<Vec<String>> = self.send::<String, Vec<String>>(req)?;
-        Ok(res.result)
+    pub fn z_listaddresses(&self) -> ZResult<Vec<String>> {
+        self.send::<String, Vec<String>>(ZRequest::z_listaddresses())
     }
 
-    pub fn z_listreceivedbyaddress(&self, addr: &str) -> Result<Vec<(Tx, String)>, Error> {
-        let req = ZRequest::<String>::builder()
-            .method("z_listreceivedbyaddress".to_string())
-            .params(vec![addr.to_owned()])
-            .build();
-        let res: ZResponse<Vec<Tx>> = self.send::<String, Vec<Tx>>(req)?;
-        let result_pairs = res.result.iter().map(|tx| (tx.clone(), addr.to_string())).collect::<Vec<_>>();
-        Ok(result_pairs)
+    /// Notes received on `addr` with at least `minconf` confirmations; 0
+    /// includes those still in the mempool.
+    pub fn z_listreceivedbyaddress(&self, addr: &str, minconf: u32) -> ZResult<Vec<(Tx, String)>> {
+        let req = ZRequest::z_listreceivedbyaddress(addr, minconf);
+        let txs: Vec<Tx> = self.send::<serde_json::Value, Vec<Tx>>(req)?;
+        Ok(pair_with_addr(txs, addr))
     }
 
-    pub fn gettransaction(&self, txid: &str) -> Result<WalletTx, Error> {
-        let req = ZRequest::<String>::builder()
-            .method("gettransaction".to_string())
-            .params(vec![txid.to_owned()])
-            .build();
-        let res: ZResponse<WalletTx> = self.send::<String, WalletTx>(req)?;
-        Ok(res.result)
+    /// Batched `z_listreceivedbyaddress` over several addresses.
+    pub fn z_listreceivedbyaddresses(&self, addrs: &[String], minconf: u32) -> ZResult<Vec<(Tx, String)>> {
+        let reqs = addrs.iter().map(|addr| ZRequest::z_listreceivedbyaddress(addr, minconf)).collect();
+        let results = self.batch::<serde_json::Value, Vec<Tx>>(reqs)?;
+        collect_received(results, addrs)
     }
 
-    pub fn z_sendmany(
-        &self, 
-        sender_addr: &str,
-        receiver_addr: &str, 
-        amount: f32,
-        memo: String
-    ) -> Result<String, Error> {
-        let req = ZRequest::<serde_json::Value>::builder()
-            .method("z_sendmany".to_string())
-            .params(vec![
-                serde_json::Value::String(sender_addr.to_string()),
-                serde_json::Value::Array(vec![
-                    serde_json::json!({
-                        "address": receiver_addr,
-                        "amount": amount,
-                        "memo": memo
-                    }),
-                ])
-            ])
-            .build();
-        let res = self.send::<serde_json::Value, String>(req)?;
-        Ok(res.result)
+    /// Height of the wallet's best block.
+    pub fn getblockcount(&self) -> ZResult<usize> {
+        self.send::<String, usize>(ZRequest::getblockcount())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::hex::*;
-    use super::*;
-    use httpmock::MockServer;
-    use httpmock::Method::POST;
-    use serde_json::{self, json, Value::Null};
-    
-    #[test]
-    fn test_getbalance() {
-        let server = MockServer::start();
-        let getbalance_mock = server.mock(|when, then| {
-            when.method(POST)
-                .path("/getbalance")
-                .body(json!({
-                    "jsonrpc": "1.0", 
-                    "method": "getbalance", 
-                    "params": serde_json::Value::Array(vec![])
-                }).to_string());
-            then.status(200)
-                .header("Content-Type", "application/json")
-                .body(json!({
-                    "result": 2.5,
-                    "error": Null,
-                    "id": Null,
-                }).to_string());
-        });
+    /// Funds of `addr` in notes with at least `minconf` confirmations.
+    pub fn z_getbalance(&self, addr: &str, minconf: u32) -> ZResult<Amount> {
+        self.send::<serde_json::Value, Amount>(ZRequest::z_getbalance(addr, minconf))
+    }
 
-        let balance = ZClient::builder()
-            .with_url(server.url("/getbalance"))
-            .expect("Failed to parse URL")
-            .with_auth("user".to_string(), Some("pass".to_string()))
-            .build()
-            .getbalance()
-            .expect("Failed to build client");
+    /// Batched `z_getbalance` over several addresses, in the same order.
+    pub fn z_getbalances(&self, addrs: &[String], minconf: u32) -> ZResult<Vec<Amount>> {
+        let reqs = addrs.iter().map(|addr| ZRequest::z_getbalance(addr, minconf)).collect();
+        self.batch::<serde_json::Value, Amount>(reqs)?.into_iter().collect()
+    }
 
-        assert!(balance == 2.5);
+    /// Funds of the whole wallet in notes with at least `minconf`
+    /// confirmations.
+    pub fn z_gettotalbalance(&self, minconf: u32) -> ZResult<TotalBalance> {
+        self.send::<serde_json::Value, TotalBalance>(ZRequest::z_gettotalbalance(minconf))
     }
 
-    #[test]
-    fn test_z_listaddresses() {
-        let server = MockServer::start();
+    pub fn z_listaccounts(&self) -> ZResult<Vec<Account>> {
+        self.send::<String, Vec<Account>>(ZRequest::z_listaccounts())
+    }
 
-        let listaddresses_mock = server.mock(|when, then| {
-            when.method(POST)
-                .path("/z_listaddresses")
-                .body(json!({
-                    "jsonrpc": "1.0", 
-                    "method": "z_listaddresses", 
-                    "params": serde_json::Value::Array(vec![])
-                }).to_string());
-            then.status(200)
-                .header("Content-Type", "application/json")
-                .body(json!({
-                    "result": vec!["z_addr1", "z_addr2", "z_addr3"],
-                    "error": Null,
-                    "id": Null,
-                }).to_string());
-        });
+    /// Unspent notes of `addrs` with at least `minconf` confirmations.
+    pub fn z_listunspent(&self, minconf: u32, addrs: &[String]) -> ZResult<Vec<UnspentNote>> {
+        self.send::<serde_json::Value, Vec<UnspentNote>>(ZRequest::z_listunspent(minconf, addrs))
+    }
 
-        let addresses = ZClient::builder()
-            .with_url(server.url("/z_listaddresses"))
-            .expect("Failed to parse URL")
-            .with_auth("user".to_string(), Some("pass".to_string()))
-            .build()
-            .z_listaddresses()
-            .expect("Failed to build client");
+    /// Funds of `account` in each pool, in notes with at least `minconf`
+    /// confirmations.
+    pub fn z_getbalanceforaccount(&self, account: u32, minconf: u32) -> ZResult<AccountBalance> {
+        let req = ZRequest::z_getbalanceforaccount(account, minconf);
+        self.send::<serde_json::Value, AccountBalance>(req)
+    }
 
-        assert!(addresses == vec![
-            "z_addr1".to_string(), 
-            "z_addr2".to_string(), 
-            "z_addr3".to_string(),
-        ]);
+    /// Create a Sapling address.
+    pub fn z_getnewaddress(&self) -> ZResult<String> {
+        self.send::<String, String>(ZRequest::z_getnewaddress())
     }
 
-    #[test]
+    pub fn z_getnewaccount(&self) -> ZResult<NewAccount> {
+        self.send::<String, NewAccount>(ZRequest::z_getnewaccount())
+    }
+
+    /// Derive the next unified address of `account`.
+    pub fn z_getaddressforaccount(&self, account: u32) -> ZResult<UnifiedAddress> {
+        self.send::<serde_json::Value, UnifiedAddress>(ZRequest::z_getaddressforaccount(account))
+    }
+
+    pub fn gettransaction(&self, txid: &str) -> ZResult<WalletTx> {
+        self.send::<String, WalletTx>(ZRequest::gettransaction(txid))
+    }
+
+    /// Batched `gettransaction` over several txids, in the same order.
+    pub fn gettransactions(&self, txids: &[String]) -> ZResult<Vec<WalletTx>> {
+        let reqs = txids.iter().map(|txid| ZRequest::gettransaction(txid)).collect();
+        self.batch::<String, WalletTx>(reqs)?.into_iter().collect()
+    }
+
+    pub fn z_viewtransaction(&self, txid: &str) -> ZResult<ViewTx> {
+        self.send::<String, ViewTx>(ZRequest::z_viewtransaction(txid))
+    }
+
+    /// Batched `z_viewtransaction` over several txids, in the same order.
+    pub fn z_viewtransactions(&self, txids: &[String]) -> ZResult<Vec<ViewTx>> {
+        let reqs = txids.iter().map(|txid| ZRequest::z_viewtransaction(txid)).collect();
+        self.batch::<String, ViewTx>(reqs)?.into_iter().collect()
+    }
+
+    /// Send all `outputs` from `sender_addr` in a single transaction.
+    pub fn z_sendmany(
+        &self,
+        sender_addr: &str,
+        outputs: &[Output],
+        opts: &SendOptions,
+    ) -> ZResult<String> {
+        let req = ZRequest::z_sendmany(sender_addr, outputs, opts);
+        self.send::<serde_json::Value, String>(req)
+    }
+
+    /// Status of the given operations, which stay known to zcashd.
+    pub fn z_getoperationstatus(&self, opids: &[String]) -> ZResult<Vec<Operation>> {
+        self.send::<serde_json::Value, Vec<Operation>>(ZRequest::z_getoperationstatus(opids))
+    }
+
+    /// Status of the given finished operations, which zcashd then forgets.
+    pub fn z_getoperationresult(&self, opids: &[String]) -> ZResult<Vec<Operation>> {
+        self.send::<serde_json::Value, Vec<Operation>>(ZRequest::z_getoperationresult(opids))
+    }
+}
+
+/// Decode a response body, falling back to HTTP errors when it is not JSON.
+fn parse_body<R: DeserializeOwned>(status: StatusCode, body: &str) -> ZResult<R> {
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(ZRpcError::Auth);
+    }
+    match serde_json::from_str::<R>(body) {
+        Ok(res) => Ok(res),
+        Err(_) if !status.is_success() => Err(ZRpcError::Http{
+            status: status.as_u16(),
+            body: body.to_string(),
+        }),
+        Err(e) => Err(ZRpcError::Decode(e)),
+    }
+}
+
+/// Number the requests of a batch so responses can be matched by id.
+fn number_batch<S>(reqs: Vec<ZRequest<S>>) -> Vec<ZRequest<S>> {
+    reqs.into_iter()
+        .enumerate()
+        .map(|(i, mut req)| {
+            req.id = Some(i as i32);
+            req
+        })
+        .collect::<Vec<_>>()
+}
+
+/* Request constructors shared by the blocking and async clients */
+
+impl ZRequest<String> {
+    pub fn getbalance() -> Self {
+        Self::builder()
+            .method("getbalance".to_string())
+            .build()
+    }
+
+    pub fn z_listaddresses() -> Self {
+        Self::builder()
+            .method("z_listaddresses".to_string())
+            .build()
+    }
+
+    pub fn getblockcount() -> Self {
+        Self::builder()
+            .method("getblockcount".to_string())
+            .build()
+    }
+
+    pub fn z_listaccounts() -> Self {
+        Self::builder()
+            .method("z_listaccounts".to_string())
+            .build()
+    }
+
+    pub fn z_getnewaddress() -> Self {
+        Self::builder()
+            .method("z_getnewaddress".to_string())
+            .params(vec!["sapling".to_string()])
+            .build()
+    }
+
+    pub fn z_getnewaccount() -> Self {
+        Self::builder()
+            .method("z_getnewaccount".to_string())
+            .build()
+    }
+
+    pub fn gettransaction(txid: &str) -> Self {
+        Self::builder()
+            .method("gettransaction".to_string())
+            .params(vec![txid.to_owned()])
+            .build()
+    }
+
+    pub fn z_viewtransaction(txid: &str) -> Self {
+        Self::builder()
+            .method("z_viewtransaction".to_string())
+            .params(vec![txid.to_owned()])
+            .build()
+    }
+}
+
+impl ZRequest<serde_json::Value> {
+    pub fn z_getbalance(addr: &str, minconf: u32) -> Self {
+        Self::builder()
+            .method("z_getbalance".to_string())
+            .params(vec![json!(addr), json!(minconf)])
+            .build()
+    }
+
+    pub fn z_gettotalbalance(minconf: u32) -> Self {
+        Self::builder()
+            .method("z_gettotalbalance".to_string())
+            .params(vec![json!(minconf)])
+            .build()
+    }
+
+    pub fn z_getbalanceforaccount(account: u32, minconf: u32) -> Self {
+        Self::builder()
+            .method("z_getbalanceforaccount".to_string())
+            .params(vec![json!(account), json!(minconf)])
+            .build()
+    }
+
+    pub fn z_listunspent(minconf: u32, addrs: &[String]) -> Self {
+        Self::builder()
+            .method("z_listunspent".to_string())
+            .params(vec![json!(minconf), json!(UNSPENT_MAXCONF), json!(false), json!(addrs)])
+            .build()
+    }
+
+    pub fn z_getaddressforaccount(account: u32) -> Self {
+        Self::builder()
+            .method("z_getaddressforaccount".to_string())
+            .params(vec![json!(account)])
+            .build()
+    }
+
+    pub fn z_listreceivedbyaddress(addr: &str, minconf: u32) -> Self {
+        Self::builder()
+            .method("z_listreceivedbyaddress".to_string())
+            .params(vec![json!(addr), json!(minconf)])
+            .build()
+    }
+
+    pub fn z_getoperationstatus(opids: &[String]) -> Self {
+        Self::builder()
+            .method("z_getoperationstatus".to_string())
+            .params(vec![json!(opids)])
+            .build()
+    }
+
+    pub fn z_getoperationresult(opids: &[String]) -> Self {
+        Self::builder()
+            .method("z_getoperationresult".to_string())
+            .params(vec![json!(opids)])
+            .build()
+    }
+
+    pub fn z_sendmany(sender_addr: &str, outputs: &[Output], opts: &SendOptions) -> Self {
+        let mut params = vec![
+            serde_json::Value::String(sender_addr.to_string()),
+            json!(outputs),
+        ];
+        // Arguments are positional: give minconf and fee whenever a later
+        // one is set. A null fee selects the ZIP-317 conventional fee.
+        if opts.minconf.is_some() || opts.fee.is_some() || opts.privacy_policy.is_some() {
+            params.push(json!(opts.minconf.unwrap_or(DEFAULT_SEND_MINCONF)));
+        }
+        if opts.fee.is_some() || opts.privacy_policy.is_some() {
+            params.push(json!(opts.fee));
+        }
+        if let Some(policy) = opts.privacy_policy {
+            params.push(json!(policy));
+        }
+        Self::builder()
+            .method("z_sendmany".to_string())
+            .params(params)
+            .build()
+    }
+}
+
+/// Tag every received note with the address it was received by.
+fn pair_with_addr(txs: Vec<Tx>, addr: &str) -> Vec<(Tx, String)> {
+    txs.into_iter().map(|tx| (tx, addr.to_string())).collect::<Vec<_>>()
+}
+
+/// Flatten batched `z_listreceivedbyaddress` results, failing on the first error.
+fn collect_received(results: Vec<ZResult<Vec<Tx>>>, addrs: &[String]) -> ZResult<Vec<(Tx, String)>> {
+    let mut pairs = vec![];
+    for (txs, addr) in results.into_iter().zip(addrs) {
+        pairs.extend(pair_with_addr(txs?, addr));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hex::*;
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::Method::POST;
+    use serde_json::{self, json, Value::Null};
+    
+    #[test]
+    fn test_getbalance() {
+        let server = MockServer::start();
+        let getbalance_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/getbalance")
+                .body(json!({
+                    "jsonrpc": "1.0", 
+                    "method": "getbalance", 
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": 2.5,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let balance = ZClient::builder()
+            .with_url(server.url("/getbalance"))
+            .expect("Failed to parse URL")
+            .with_auth("user".to_string(), Some("pass".to_string()))
+            .build()
+            .getbalance()
+            .expect("Failed to build client");
+
+        getbalance_mock.assert();
+        assert!(balance == Amount::from_zat(250_000_000));
+    }
+
+    #[test]
+    fn test_getblockcount() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "getblockcount",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": 1400579,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let height = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build()
+            .getblockcount()
+            .expect("Failed to get block count");
+
+        mock.assert();
+        assert!(height == 1400579);
+    }
+
+    #[test]
+    fn test_z_getbalances() {
+        let server = MockServer::start();
+        let balances_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "z_getbalance", "params": ["z_addr1", 10], "id": 0 },
+                    { "jsonrpc": "1.0", "method": "z_getbalance", "params": ["z_addr2", 10], "id": 1 },
+                ]).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([
+                    { "result": 1.5, "error": Null, "id": 1 },
+                    { "result": 0.0, "error": Null, "id": 0 },
+                ]).to_string());
+        });
+        let total_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/total")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_gettotalbalance",
+                    "params": [1]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": { "transparent": "0.25", "private": "1.5", "total": "1.75" },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let balances = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_getbalances(&["z_addr1".to_string(), "z_addr2".to_string()], 10)
+            .expect("Failed to get balances");
+        balances_mock.assert();
+        assert!(balances == vec![Amount::ZERO, Amount::from_zat(150_000_000)]);
+
+        let total = ZClient::builder()
+            .with_url(server.url("/total"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_gettotalbalance(1)
+            .expect("Failed to get total balance");
+        total_mock.assert();
+        assert!(total.transparent == Amount::from_zat(25_000_000));
+        assert!(total.total == Amount::from_zat(175_000_000));
+    }
+
+    #[test]
+    fn test_accounts() {
+        let server = MockServer::start();
+        let accounts_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/accounts")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_listaccounts",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{ "account": 0, "addresses": [{ "diversifier_index": 0, "ua": "u1abc" }] }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+        let balance_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/balance")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getbalanceforaccount",
+                    "params": [0, 1]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": {
+                        "pools": { "orchard": { "valueZat": 150000000 } },
+                        "minimum_confirmations": 1,
+                    },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let accounts = ZClient::builder()
+            .with_url(server.url("/accounts"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_listaccounts()
+            .expect("Failed to list accounts");
+        accounts_mock.assert();
+        assert!(accounts[0].account == 0);
+        assert!(accounts[0].addresses[0].ua == "u1abc");
+
+        let balance = ZClient::builder()
+            .with_url(server.url("/balance"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_getbalanceforaccount(0, 1)
+            .expect("Failed to get account balance");
+        balance_mock.assert();
+        assert!(balance.pools.orchard == Some(PoolBalance{ value_zat: 150_000_000 }));
+        assert!(balance.pools.sapling.is_none());
+    }
+
+    #[test]
+    fn test_z_listunspent() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_listunspent",
+                    "params": [10, 9999999, false, ["zs1me"]]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{
+                        "txid": "tx1",
+                        "pool": "sapling",
+                        "outindex": 0,
+                        "confirmations": 12,
+                        "spendable": true,
+                        "address": "zs1me",
+                        "amount": 0.0005,
+                        "amountZat": 50000,
+                        "memo": "f6",
+                        "change": false,
+                    }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let notes = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_listunspent(10, &["zs1me".to_string()])
+            .expect("Failed to list unspent notes");
+
+        mock.assert();
+        assert!(notes[0].amount == Amount::from_zat(50_000));
+        assert!(notes[0].address.as_deref() == Some("zs1me"));
+        assert!(notes[0].spendable);
+    }
+
+    #[test]
+    fn test_new_addresses() {
+        let server = MockServer::start();
+        let sapling_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/sapling")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getnewaddress",
+                    "params": ["sapling"]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({ "result": "zs1new", "error": Null, "id": Null }).to_string());
+        });
+        let account_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/account")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getnewaccount",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({ "result": { "account": 1 }, "error": Null, "id": Null }).to_string());
+        });
+        let unified_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/unified")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getaddressforaccount",
+                    "params": [1]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": {
+                        "account": 1,
+                        "diversifier_index": 0,
+                        "receiver_types": ["sapling", "orchard"],
+                        "address": "u1new",
+                    },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+        let client = |path: &str| ZClient::builder()
+            .with_url(server.url(path))
+            .expect("Failed to parse URL")
+            .build();
+
+        assert!(client("/sapling").z_getnewaddress().unwrap() == "zs1new");
+        sapling_mock.assert();
+        assert!(client("/account").z_getnewaccount().unwrap() == NewAccount{ account: 1 });
+        account_mock.assert();
+        let ua = client("/unified").z_getaddressforaccount(1).unwrap();
+        unified_mock.assert();
+        assert!(ua.address == "u1new");
+        assert!(ua.receiver_types == ["sapling", "orchard"]);
+
+        assert!("Unified".parse::<AddressType>().unwrap() == AddressType::Unified);
+        assert!("sprout".parse::<AddressType>().is_err());
+    }
+
+    #[test]
+    fn test_send_fee() {
+        let opts = SendOptions::default();
+        assert!(opts.min_confirmations() == 10);
+        assert!(opts.fee_for(1) == Amount::from_zat(10_000));
+        assert!(opts.fee_for(3) == Amount::from_zat(20_000));
+        let opts = SendOptions{ fee: Some(Amount::from_zat(1_000)), minconf: Some(1), ..SendOptions::default() };
+        assert!(opts.fee_for(3) == Amount::from_zat(1_000));
+        assert!(opts.min_confirmations() == 1);
+    }
+
+    #[test]
+    fn test_z_listaddresses() {
+        let server = MockServer::start();
+
+        let listaddresses_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/z_listaddresses")
+                .body(json!({
+                    "jsonrpc": "1.0", 
+                    "method": "z_listaddresses", 
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": vec!["z_addr1", "z_addr2", "z_addr3"],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let addresses = ZClient::builder()
+            .with_url(server.url("/z_listaddresses"))
+            .expect("Failed to parse URL")
+            .with_auth("user".to_string(), Some("pass".to_string()))
+            .build()
+            .z_listaddresses()
+            .expect("Failed to build client");
+
+        listaddresses_mock.assert();
+        assert!(addresses == vec![
+            "z_addr1".to_string(), 
+            "z_addr2".to_string(), 
+            "z_addr3".to_string(),
+        ]);
+    }
+
+    #[test]
     fn test_z_listreceivedbyaddress() {
         let expected = Tx{
             txid: "90ac85f44c412b43db85d2c52e1ccafeea6385661f4b58cb8dd372cac73d1978".to_owned(),
-            amount: 0.01,
+            amount: Amount::from_zat(1000000),
             amount_zat: 1000000,
             memo: "68656c6c6f207a63617368000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000".to_owned(),
             outindex: 0,
             confirmations: 7651,
-            blockheight: 1400579,
-            blockindex: 1,
-            blocktime: 1620543097,
+            blockheight: Some(1400579),
+            blockindex: Some(1),
+            blocktime: Some(1620543097),
             change: false,
             jsindex: None,
             jsoutindex: None,
@@ -373,13 +1411,23 @@ mod tests {
                 .body(json!({
                     "jsonrpc": "1.0", 
                     "method": "z_listreceivedbyaddress", 
-                    "params": vec!["z_addr"]
+                    "params": ["z_addr", 0]
                 }).to_string());
             then.status(200)
                 .header("Content-Type", "application/json")
                 .body(json!({
                     "result": vec![
-                        json!(expected)
+                        json!(expected),
+                        // Not mined yet.
+                        json!({
+                            "txid": "aa".repeat(32),
+                            "amount": 0.0001,
+                            "amountZat": 10000,
+                            "memo": "f6",
+                            "outindex": 1,
+                            "confirmations": 0,
+                            "change": false,
+                        }),
                     ],
                     "error": Null,
                     "id": Null
@@ -391,13 +1439,17 @@ mod tests {
             .expect("Failed to parse URL")
             .with_auth("user".to_string(), Some("pass".to_string()))
             .build()
-            .z_listreceivedbyaddress("z_addr")
+            .z_listreceivedbyaddress("z_addr", 0)
             .expect("Failed to build client");
         
+        mock.assert();
         let (tx, addr) = &txs[0];
         assert!(tx == &expected);
         assert!(addr == "z_addr");
         assert!(hex_to_string(&tx.memo).unwrap().starts_with("hello zcash"));
+        let (pending, _) = &txs[1];
+        assert!(pending.confirmations == 0);
+        assert!(pending.blockheight.is_none() && pending.blocktime.is_none());
     }
 
     #[test]
@@ -432,14 +1484,106 @@ mod tests {
             .gettransaction(&txid)
             .expect("Failed to build client");
 
+        mock.assert();
         assert!(wtx == expected_wtx);
     }
 
+    #[test]
+    fn test_gettransaction_pending() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": pending_wtx(),
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let wtx = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .with_auth("user".to_string(), Some("pass".to_string()))
+            .build()
+            .gettransaction("mempooltx")
+            .expect("Failed to decode a transaction not mined yet");
+
+        mock.assert();
+        assert!(wtx.confirmations == 0);
+        assert!(wtx.blockhash.is_none() && wtx.blockindex.is_none() && wtx.blocktime.is_none());
+    }
+
+    #[test]
+    fn test_z_viewtransaction() {
+        let txid = "d1ffa3a5c3b1b1e2f0e1a7c9d8b6a4f2e0c8a6b4d2f0e8c6a4b2d0f8e6c4a2b0";
+        let data = json!({
+            "jsonrpc": "1.0",
+            "method": "z_viewtransaction",
+            "params": vec![txid]
+        }).to_string();
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(data);
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": {
+                        "txid": txid,
+                        "spends": [],
+                        "outputs": [
+                            {
+                                "type": "sapling",
+                                "output": 0,
+                                "address": "recv_addr",
+                                "outgoing": true,
+                                "walletInternal": false,
+                                "value": 0.0001,
+                                "valueZat": 10000,
+                                "memo": "68656c6c6f207a63617368",
+                                "memoStr": "hello zcash",
+                            },
+                            {
+                                "type": "sapling",
+                                "output": 1,
+                                "outgoing": false,
+                                "walletInternal": true,
+                                "value": 0.5,
+                                "valueZat": 50000000,
+                                "memo": "f6",
+                            },
+                        ],
+                    },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let vtx = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_viewtransaction(txid)
+            .expect("Failed to view transaction");
+
+        mock.assert();
+        assert!(vtx.txid == txid);
+        assert!(vtx.outputs.len() == 2);
+        assert!(vtx.outputs[0].outgoing && vtx.outputs[0].address.as_deref() == Some("recv_addr"));
+        assert!(vtx.outputs[0].value == Amount::from_zat(10_000));
+        assert!(vtx.outputs[1].wallet_internal && vtx.outputs[1].address.is_none());
+    }
+
     #[test]
     fn test_z_sendmany() {
         let expected_opid = "opid-f757ae55-530b-4499-a1e2-12fd32c96a36";
         let memo = "68656c6c6f207a63617368";
-        let amount: f32 = 2.99;
+        let amount = Amount::from_zat(299_000_000);
         let sender = "sender_addr";
         let recv = "recv_addr";
 
@@ -477,10 +1621,287 @@ mod tests {
             .expect("Failed to parse URL")
             .with_auth("user".to_string(), Some("pass".to_string()))
             .build()
-            .z_sendmany(sender, recv, amount, memo.to_string())
+            .z_sendmany(sender, &[Output::new(recv, amount, Some(memo.to_string()))], &SendOptions::default())
             .expect("Failed to build client");
 
+        mock.assert();
         assert!(opid == expected_opid);
     }
+
+    #[test]
+    fn test_batch() {
+        let mut wtx_a = WalletTx::mock();
+        wtx_a.txid = "txid_a".to_string();
+        let mut wtx_b = WalletTx::mock();
+        wtx_b.txid = "txid_b".to_string();
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "gettransaction", "params": ["txid_a"], "id": 0 },
+                    { "jsonrpc": "1.0", "method": "gettransaction", "params": ["txid_x"], "id": 1 },
+                    { "jsonrpc": "1.0", "method": "gettransaction", "params": ["txid_b"], "id": 2 },
+                ]).to_string());
+            // zcashd is free to answer batch entries in any order.
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([
+                    { "result": wtx_b, "error": Null, "id": 2 },
+                    { "result": Null, "error": { "code": -5, "message": "Invalid or non-wallet transaction id" }, "id": 1 },
+                    { "result": wtx_a, "error": Null, "id": 0 },
+                ]).to_string());
+        });
+
+        let client = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build();
+        let reqs = ["txid_a", "txid_x", "txid_b"].iter()
+            .map(|txid| ZRequest::gettransaction(txid))
+            .collect();
+        let results = client.batch::<String, WalletTx>(reqs).expect("Failed to send batch");
+
+        mock.assert();
+        assert!(results.len() == 3);
+        assert!(results[0].as_ref().unwrap() == &wtx_a);
+        assert!(matches!(results[1], Err(ZRpcError::InvalidAddress(_))));
+        assert!(results[2].as_ref().unwrap() == &wtx_b);
+
+        let err = client.gettransactions(&["txid_a".to_string(), "txid_x".to_string(), "txid_b".to_string()])
+            .expect_err("Expected the failed entry to fail the whole lookup");
+        assert!(matches!(err, ZRpcError::InvalidAddress(_)));
+    }
+
+    #[test]
+    fn test_z_listreceivedbyaddresses() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "z_listreceivedbyaddress", "params": ["z_addr1", 1], "id": 0 },
+                    { "jsonrpc": "1.0", "method": "z_listreceivedbyaddress", "params": ["z_addr2", 1], "id": 1 },
+                ]).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([
+                    { "result": [], "error": Null, "id": 0 },
+                    { "result": [], "error": Null, "id": 1 },
+                ]).to_string());
+        });
+
+        let txs = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_listreceivedbyaddresses(&["z_addr1".to_string(), "z_addr2".to_string()], 1)
+            .expect("Failed to list received notes");
+
+        mock.assert();
+        assert!(txs.is_empty());
+
+        // An empty batch never reaches the node.
+        let txs = ZClient::builder()
+            .with_url(server.url("/unused"))
+            .expect("Failed to parse URL")
+            .build()
+            .z_listreceivedbyaddresses(&[], 1)
+            .expect("Failed to skip empty batch");
+        assert!(txs.is_empty());
+    }
+
+    #[test]
+    fn test_z_getoperationstatus() {
+        let opid = "opid-f757ae55-530b-4499-a1e2-12fd32c96a36";
+        let server = MockServer::start();
+        let status_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getoperationstatus",
+                    "params": [[opid]]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{
+                        "id": opid,
+                        "status": "executing",
+                        "creation_time": 1620543097,
+                        "method": "z_sendmany",
+                        "params": {},
+                    }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+        let result_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getoperationresult",
+                    "params": [[opid]]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{
+                        "id": opid,
+                        "status": "failed",
+                        "creation_time": 1620543097,
+                        "error": { "code": -6, "message": "Insufficient funds" },
+                        "execution_secs": 0.5,
+                    }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        });
+
+        let client = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .build();
+
+        let ops = client.z_getoperationstatus(&[opid.to_string()]).expect("Failed to get status");
+        status_mock.assert();
+        assert!(ops[0].status == OperationState::Executing);
+        assert!(!ops[0].is_finished());
+
+        let ops = client.z_getoperationresult(&[opid.to_string()]).expect("Failed to get result");
+        result_mock.assert();
+        assert!(ops[0].is_finished());
+        assert!(ops[0].result.is_none());
+        assert!(ops[0].error.as_ref().unwrap().message == "Insufficient funds");
+    }
+
+    #[test]
+    fn test_z_sendmany_many_outputs() {
+        let outputs = vec![
+            Output::new("recv_addr1", Amount::from_zat(10_000), Some("6869".to_string())),
+            Output::new("recv_addr2", Amount::from_zat(20_000), Some("6869".to_string())),
+            Output::new("t_addr", Amount::from_zat(30_000), None),
+        ];
+        let req = ZRequest::z_sendmany("sender_addr", &outputs, &SendOptions::default());
+        assert!(json!(req)["params"] == json!([
+            "sender_addr",
+            [
+                { "address": "recv_addr1", "amount": "0.0001", "memo": "6869" },
+                { "address": "recv_addr2", "amount": "0.0002", "memo": "6869" },
+                { "address": "t_addr", "amount": "0.0003" },
+            ],
+        ]));
+    }
+
+    #[test]
+    fn test_z_sendmany_options() {
+        let outputs = vec![Output::new("recv_addr", Amount::from_zat(10_000), Some("".to_string()))];
+        let output = json!({ "address": "recv_addr", "amount": "0.0001", "memo": "" });
+        let opts = SendOptions{
+            privacy_policy: Some(PrivacyPolicy::AllowRevealedAmounts),
+            ..SendOptions::default()
+        };
+        let req = ZRequest::z_sendmany("sender_addr", &outputs, &opts);
+        assert!(json!(req) == json!({
+            "jsonrpc": "1.0",
+            "method": "z_sendmany",
+            "params": ["sender_addr", [output], 10, Null, "AllowRevealedAmounts"],
+        }));
+
+        let opts = SendOptions{
+            minconf: Some(1),
+            fee: Some(Amount::from_zat(15_000)),
+            privacy_policy: None,
+        };
+        let req = ZRequest::z_sendmany("sender_addr", &outputs, &opts);
+        assert!(json!(req)["params"] == json!(["sender_addr", [output], 1, "0.00015"]));
+
+        assert!("allowrevealedsenders".parse::<PrivacyPolicy>().unwrap() == PrivacyPolicy::AllowRevealedSenders);
+        assert!("Paranoid".parse::<PrivacyPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_rpc_error_codes() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/");
+            then.status(500)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": Null,
+                    "error": {
+                        "code": -6,
+                        "message": "Insufficient funds, have 0.00, need 0.0001",
+                    },
+                    "id": Null,
+                }).to_string());
+        });
+
+        let err = ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .with_auth("user".to_string(), Some("pass".to_string()))
+            .build()
+            .z_sendmany("sender_addr", &[Output::new("recv_addr", Amount::from_zat(10_000), None)], &SendOptions::default())
+            .expect_err("Expected an RPC error");
+
+        mock.assert();
+        assert!(matches!(err, ZRpcError::InsufficientFunds(_)));
+        assert!(err.code() == Some(-6));
+        assert!(matches!(ZRpcError::from_code(-32601, "".to_string()), ZRpcError::MethodNotFound(_)));
+        assert!(matches!(ZRpcError::from_code(-5, "".to_string()), ZRpcError::InvalidAddress(_)));
+        assert!(matches!(ZRpcError::from_code(-13, "".to_string()), ZRpcError::WalletLocked(_)));
+        assert!(matches!(ZRpcError::from_code(-8, "".to_string()), ZRpcError::Rpc{ code: -8, .. }));
+        assert!(!err.is_transient());
+        assert!(ZRpcError::from_code(-28, "Loading block index...".to_string()).is_transient());
+    }
+
+    #[test]
+    fn test_http_errors() {
+        let server = MockServer::start();
+        let auth_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/auth");
+            then.status(401);
+        });
+        let crash_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/crash");
+            then.status(503)
+                .body("Work queue depth exceeded");
+        });
+
+        let err = ZClient::builder()
+            .with_url(server.url("/auth"))
+            .expect("Failed to parse URL")
+            .build()
+            .getbalance()
+            .expect_err("Expected an auth error");
+        auth_mock.assert();
+        assert!(matches!(err, ZRpcError::Auth));
+        assert!(!err.is_transient());
+        assert!(err.needs_reconnect());
+
+        let err = ZClient::builder()
+            .with_url(server.url("/crash"))
+            .expect("Failed to parse URL")
+            .build()
+            .getbalance()
+            .expect_err("Expected an HTTP error");
+        crash_mock.assert();
+        assert!(err.is_transient());
+        assert!(!err.needs_reconnect());
+        match err {
+            ZRpcError::Http{ status, body } => {
+                assert!(status == 503);
+                assert!(body == "Work queue depth exceeded");
+            },
+            _ => panic!("Expected an HTTP error, got {:?}", err),
+        }
+    }
 }
 
@@ -0,0 +1,710 @@
+use reqwest::Client;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+use crate::amount::Amount;
+use super::{
+    collect_received, number_batch, pair_with_addr,
+    Account, AccountBalance, NewAccount, Operation, Output, SendOptions, TotalBalance,
+    Tx, UnifiedAddress, UnspentNote, ViewTx, WalletTx, ZRequest, ZResponse, ZResult,
+};
+
+/// Async counterpart of `ZClient`, driven by the caller's tokio runtime.
+/// Build it with `ZClient::builder().build_async()`.
+pub struct AsyncZClient {
+    c: Client,
+    pub url: String,
+    pub user: String,
+    pub password: Option<String>,
+}
+
+impl AsyncZClient {
+    pub(super) fn new(url: String, user: String, password: Option<String>) -> Self {
+        Self {
+            c: Client::default(),
+            url,
+            user,
+            password,
+        }
+    }
+
+    async fn send<S, T>(&self, req: ZRequest<S>) -> ZResult<T>
+    where S: Serialize + 'static, T: DeserializeOwned + 'static {
+        let res = self.c.post(self.url.clone())
+            .basic_auth(self.user.clone(), self.password.clone())
+            .header(CONTENT_TYPE, "text/octet-stream")
+            .body(json!(req).to_string())
+            .send()
+            .await?;
+        let status = res.status();
+        let body = res.text().await?;
+        ZResponse::<T>::from_body(status, &body)
+    }
+
+    /// Send several requests in a single POST. Results are returned in
+    /// request order; each one fails or succeeds on its own.
+    pub async fn batch<S, T>(&self, reqs: Vec<ZRequest<S>>) -> ZResult<Vec<ZResult<T>>>
+    where S: Serialize + 'static, T: DeserializeOwned + 'static {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        let len = reqs.len();
+        let res = self.c.post(self.url.clone())
+            .basic_auth(self.user.clone(), self.password.clone())
+            .header(CONTENT_TYPE, "text/octet-stream")
+            .body(json!(number_batch(reqs)).to_string())
+            .send()
+            .await?;
+        let status = res.status();
+        let body = res.text().await?;
+        ZResponse::<T>::batch_from_body(status, &body, len)
+    }
+
+    /* Zcash RPC API implementation */
+
+    pub async fn getbalance(&self) -> ZResult<Amount> {
+        self.send::<String, Amount>(ZRequest::getbalance()).await
+    }
+
+    pub async fn z_listaddresses(&self) -> ZResult<Vec<String>> {
+        self.send::<String, Vec<String>>(ZRequest::z_listaddresses()).await
+    }
+
+    /// Notes received on `addr` with at least `minconf` confirmations; 0
+    /// includes those still in the mempool.
+    pub async fn z_listreceivedbyaddress(&self, addr: &str, minconf: u32) -> ZResult<Vec<(Tx, String)>> {
+        let req = ZRequest::z_listreceivedbyaddress(addr, minconf);
+        let txs: Vec<Tx> = self.send::<serde_json::Value, Vec<Tx>>(req).await?;
+        Ok(pair_with_addr(txs, addr))
+    }
+
+    /// Batched `z_listreceivedbyaddress` over several addresses.
+    pub async fn z_listreceivedbyaddresses(&self, addrs: &[String], minconf: u32) -> ZResult<Vec<(Tx, String)>> {
+        let reqs = addrs.iter().map(|addr| ZRequest::z_listreceivedbyaddress(addr, minconf)).collect();
+        let results = self.batch::<serde_json::Value, Vec<Tx>>(reqs).await?;
+        collect_received(results, addrs)
+    }
+
+    /// Height of the wallet's best block.
+    pub async fn getblockcount(&self) -> ZResult<usize> {
+        self.send::<String, usize>(ZRequest::getblockcount()).await
+    }
+
+    /// Funds of `addr` in notes with at least `minconf` confirmations.
+    pub async fn z_getbalance(&self, addr: &str, minconf: u32) -> ZResult<Amount> {
+        self.send::<serde_json::Value, Amount>(ZRequest::z_getbalance(addr, minconf)).await
+    }
+
+    /// Batched `z_getbalance` over several addresses, in the same order.
+    pub async fn z_getbalances(&self, addrs: &[String], minconf: u32) -> ZResult<Vec<Amount>> {
+        let reqs = addrs.iter().map(|addr| ZRequest::z_getbalance(addr, minconf)).collect();
+        self.batch::<serde_json::Value, Amount>(reqs).await?.into_iter().collect()
+    }
+
+    /// Funds of the whole wallet in notes with at least `minconf`
+    /// confirmations.
+    pub async fn z_gettotalbalance(&self, minconf: u32) -> ZResult<TotalBalance> {
+        self.send::<serde_json::Value, TotalBalance>(ZRequest::z_gettotalbalance(minconf)).await
+    }
+
+    pub async fn z_listaccounts(&self) -> ZResult<Vec<Account>> {
+        self.send::<String, Vec<Account>>(ZRequest::z_listaccounts()).await
+    }
+
+    /// Unspent notes of `addrs` with at least `minconf` confirmations.
+    pub async fn z_listunspent(&self, minconf: u32, addrs: &[String]) -> ZResult<Vec<UnspentNote>> {
+        self.send::<serde_json::Value, Vec<UnspentNote>>(ZRequest::z_listunspent(minconf, addrs)).await
+    }
+
+    /// Funds of `account` in each pool, in notes with at least `minconf`
+    /// confirmations.
+    pub async fn z_getbalanceforaccount(&self, account: u32, minconf: u32) -> ZResult<AccountBalance> {
+        let req = ZRequest::z_getbalanceforaccount(account, minconf);
+        self.send::<serde_json::Value, AccountBalance>(req).await
+    }
+
+    /// Create a Sapling address.
+    pub async fn z_getnewaddress(&self) -> ZResult<String> {
+        self.send::<String, String>(ZRequest::z_getnewaddress()).await
+    }
+
+    pub async fn z_getnewaccount(&self) -> ZResult<NewAccount> {
+        self.send::<String, NewAccount>(ZRequest::z_getnewaccount()).await
+    }
+
+    /// Derive the next unified address of `account`.
+    pub async fn z_getaddressforaccount(&self, account: u32) -> ZResult<UnifiedAddress> {
+        self.send::<serde_json::Value, UnifiedAddress>(ZRequest::z_getaddressforaccount(account)).await
+    }
+
+    pub async fn gettransaction(&self, txid: &str) -> ZResult<WalletTx> {
+        self.send::<String, WalletTx>(ZRequest::gettransaction(txid)).await
+    }
+
+    /// Batched `gettransaction` over several txids, in the same order.
+    pub async fn gettransactions(&self, txids: &[String]) -> ZResult<Vec<WalletTx>> {
+        let reqs = txids.iter().map(|txid| ZRequest::gettransaction(txid)).collect();
+        self.batch::<String, WalletTx>(reqs).await?.into_iter().collect()
+    }
+
+    pub async fn z_viewtransaction(&self, txid: &str) -> ZResult<ViewTx> {
+        self.send::<String, ViewTx>(ZRequest::z_viewtransaction(txid)).await
+    }
+
+    /// Batched `z_viewtransaction` over several txids, in the same order.
+    pub async fn z_viewtransactions(&self, txids: &[String]) -> ZResult<Vec<ViewTx>> {
+        let reqs = txids.iter().map(|txid| ZRequest::z_viewtransaction(txid)).collect();
+        self.batch::<String, ViewTx>(reqs).await?.into_iter().collect()
+    }
+
+    /// Send all `outputs` from `sender_addr` in a single transaction.
+    pub async fn z_sendmany(
+        &self,
+        sender_addr: &str,
+        outputs: &[Output],
+        opts: &SendOptions,
+    ) -> ZResult<String> {
+        let req = ZRequest::z_sendmany(sender_addr, outputs, opts);
+        self.send::<serde_json::Value, String>(req).await
+    }
+
+    /// Status of the given operations, which stay known to zcashd.
+    pub async fn z_getoperationstatus(&self, opids: &[String]) -> ZResult<Vec<Operation>> {
+        self.send::<serde_json::Value, Vec<Operation>>(ZRequest::z_getoperationstatus(opids)).await
+    }
+
+    /// Status of the given finished operations, which zcashd then forgets.
+    pub async fn z_getoperationresult(&self, opids: &[String]) -> ZResult<Vec<Operation>> {
+        self.send::<serde_json::Value, Vec<Operation>>(ZRequest::z_getoperationresult(opids)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rpc::{pending_wtx, ZClient, ZRpcError};
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::Method::POST;
+    use serde_json::{self, json, Value::Null};
+
+    fn client(server: &MockServer) -> AsyncZClient {
+        ZClient::builder()
+            .with_url(server.url("/"))
+            .expect("Failed to parse URL")
+            .with_auth("user".to_string(), Some("pass".to_string()))
+            .build_async()
+    }
+
+    #[tokio::test]
+    async fn test_getbalance() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "getbalance",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": 2.5,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let balance = client(&server)
+            .getbalance()
+            .await
+            .expect("Failed to get balance");
+
+        mock.assert_async().await;
+        assert!(balance == Amount::from_zat(250_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_z_listaddresses() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_listaddresses",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": vec!["z_addr1", "z_addr2"],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let addresses = client(&server)
+            .z_listaddresses()
+            .await
+            .expect("Failed to list addresses");
+
+        mock.assert_async().await;
+        assert!(addresses == vec!["z_addr1".to_string(), "z_addr2".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_z_listreceivedbyaddress() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_listreceivedbyaddress",
+                    "params": ["z_addr", 1]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": serde_json::Value::Array(vec![]),
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let txs = client(&server)
+            .z_listreceivedbyaddress("z_addr", 1)
+            .await
+            .expect("Failed to list received notes");
+
+        mock.assert_async().await;
+        assert!(txs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_getblockcount() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "getblockcount",
+                    "params": serde_json::Value::Array(vec![])
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": 1400579,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let height = client(&server)
+            .getblockcount()
+            .await
+            .expect("Failed to get block count");
+
+        mock.assert_async().await;
+        assert!(height == 1400579);
+    }
+
+    #[tokio::test]
+    async fn test_z_getbalances() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "z_getbalance", "params": ["z_addr1", 1], "id": 0 },
+                    { "jsonrpc": "1.0", "method": "z_getbalance", "params": ["z_addr2", 1], "id": 1 },
+                ]).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([
+                    { "result": 0.0001, "error": Null, "id": 0 },
+                    { "result": 2, "error": Null, "id": 1 },
+                ]).to_string());
+        }).await;
+
+        let balances = client(&server)
+            .z_getbalances(&["z_addr1".to_string(), "z_addr2".to_string()], 1)
+            .await
+            .expect("Failed to get balances");
+
+        mock.assert_async().await;
+        assert!(balances == vec![Amount::from_zat(10_000), Amount::from_zat(200_000_000)]);
+    }
+
+    #[tokio::test]
+    async fn test_z_gettotalbalance() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_gettotalbalance",
+                    "params": [10]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": { "transparent": "0.00", "private": "0.5", "total": "0.5" },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let total = client(&server)
+            .z_gettotalbalance(10)
+            .await
+            .expect("Failed to get total balance");
+
+        mock.assert_async().await;
+        assert!(total.private == Amount::from_zat(50_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_z_getbalanceforaccount() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getbalanceforaccount",
+                    "params": [1, 10]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": {
+                        "pools": {
+                            "transparent": { "valueZat": 5000 },
+                            "sapling": { "valueZat": 20000 },
+                        },
+                        "minimum_confirmations": 10,
+                    },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let balance = client(&server)
+            .z_getbalanceforaccount(1, 10)
+            .await
+            .expect("Failed to get account balance");
+
+        mock.assert_async().await;
+        assert!(balance.pools.sapling.map(|pool| pool.value_zat) == Some(20_000));
+        assert!(balance.pools.orchard.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_z_listunspent() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_listunspent",
+                    "params": [1, 9999999, false, ["zs1me", "zs1you"]]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{
+                        "txid": "tx1",
+                        "confirmations": 3,
+                        "spendable": true,
+                        "address": "zs1you",
+                        "amount": 0.001,
+                        "change": true,
+                    }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let notes = client(&server)
+            .z_listunspent(1, &["zs1me".to_string(), "zs1you".to_string()])
+            .await
+            .expect("Failed to list unspent notes");
+
+        mock.assert_async().await;
+        assert!(notes[0].amount == Amount::from_zat(100_000));
+        assert!(notes[0].change);
+    }
+
+    #[tokio::test]
+    async fn test_z_getaddressforaccount() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getaddressforaccount",
+                    "params": [0]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": {
+                        "account": 0,
+                        "diversifier_index": 3,
+                        "receiver_types": ["p2pkh", "sapling", "orchard"],
+                        "address": "u1fresh",
+                    },
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let ua = client(&server)
+            .z_getaddressforaccount(0)
+            .await
+            .expect("Failed to get a unified address");
+
+        mock.assert_async().await;
+        assert!(ua.address == "u1fresh");
+        assert!(ua.receiver_types.len() == 3);
+    }
+
+    #[tokio::test]
+    async fn test_gettransaction() {
+        let expected_wtx = WalletTx::mock();
+        let txid = expected_wtx.txid.clone();
+
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "gettransaction",
+                    "params": vec![txid.clone()]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": expected_wtx,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let wtx = client(&server)
+            .gettransaction(&txid)
+            .await
+            .expect("Failed to get transaction");
+
+        mock.assert_async().await;
+        assert!(wtx == expected_wtx);
+    }
+
+    #[tokio::test]
+    async fn test_gettransaction_pending() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": pending_wtx(),
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let wtx = client(&server)
+            .gettransaction("mempooltx")
+            .await
+            .expect("Failed to decode a transaction not mined yet");
+
+        mock.assert_async().await;
+        assert!(wtx.confirmations == 0);
+        assert!(wtx.blockhash.is_none() && wtx.blockindex.is_none() && wtx.blocktime.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_z_sendmany() {
+        let expected_opid = "opid-f757ae55-530b-4499-a1e2-12fd32c96a36";
+
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_sendmany",
+                    "params": vec![
+                        json!("sender_addr"),
+                        json!([{
+                            "address": "recv_addr",
+                            "amount": "2.99",
+                            "memo": "68656c6c6f"
+                        }]),
+                    ]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": expected_opid,
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let opid = client(&server)
+            .z_sendmany(
+                "sender_addr",
+                &[Output::new("recv_addr", Amount::from_zat(299_000_000), Some("68656c6c6f".to_string()))],
+                &SendOptions::default(),
+            )
+            .await
+            .expect("Failed to send");
+
+        mock.assert_async().await;
+        assert!(opid == expected_opid);
+    }
+
+    #[tokio::test]
+    async fn test_gettransactions() {
+        let mut wtx_a = WalletTx::mock();
+        wtx_a.txid = "txid_a".to_string();
+        let mut wtx_b = WalletTx::mock();
+        wtx_b.txid = "txid_b".to_string();
+
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "gettransaction", "params": ["txid_a"], "id": 0 },
+                    { "jsonrpc": "1.0", "method": "gettransaction", "params": ["txid_b"], "id": 1 },
+                ]).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([
+                    { "result": wtx_b, "error": Null, "id": 1 },
+                    { "result": wtx_a, "error": Null, "id": 0 },
+                ]).to_string());
+        }).await;
+
+        let wtxs = client(&server)
+            .gettransactions(&["txid_a".to_string(), "txid_b".to_string()])
+            .await
+            .expect("Failed to get transactions");
+
+        mock.assert_async().await;
+        assert!(wtxs == vec![wtx_a, wtx_b]);
+    }
+
+    #[tokio::test]
+    async fn test_z_viewtransactions() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!([
+                    { "jsonrpc": "1.0", "method": "z_viewtransaction", "params": ["txid_a"], "id": 0 },
+                ]).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!([{
+                    "result": {
+                        "txid": "txid_a",
+                        "spends": [],
+                        "outputs": [{
+                            "type": "sapling",
+                            "output": 0,
+                            "address": "recv_addr",
+                            "outgoing": true,
+                            "walletInternal": false,
+                            "value": 0.0001,
+                            "valueZat": 10000,
+                            "memo": "f6",
+                        }],
+                    },
+                    "error": Null,
+                    "id": 0,
+                }]).to_string());
+        }).await;
+
+        let vtxs = client(&server)
+            .z_viewtransactions(&["txid_a".to_string()])
+            .await
+            .expect("Failed to view transactions");
+
+        mock.assert_async().await;
+        assert!(vtxs.len() == 1);
+        assert!(vtxs[0].outputs[0].address.as_deref() == Some("recv_addr"));
+    }
+
+    #[tokio::test]
+    async fn test_z_getoperationresult() {
+        let opid = "opid-f757ae55-530b-4499-a1e2-12fd32c96a36";
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(json!({
+                    "jsonrpc": "1.0",
+                    "method": "z_getoperationresult",
+                    "params": [[opid]]
+                }).to_string());
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": [{
+                        "id": opid,
+                        "status": "success",
+                        "creation_time": 1620543097,
+                        "result": { "txid": "90ac85f4" },
+                        "execution_secs": 42.0,
+                    }],
+                    "error": Null,
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let ops = client(&server)
+            .z_getoperationresult(&[opid.to_string()])
+            .await
+            .expect("Failed to get result");
+
+        mock.assert_async().await;
+        assert!(ops[0].is_finished());
+        assert!(ops[0].result.as_ref().unwrap().txid == "90ac85f4");
+    }
+
+    #[tokio::test]
+    async fn test_rpc_error() {
+        let server = MockServer::start_async().await;
+        let mock = server.mock_async(|when, then| {
+            when.method(POST)
+                .path("/");
+            then.status(500)
+                .header("Content-Type", "application/json")
+                .body(json!({
+                    "result": Null,
+                    "error": { "code": -13, "message": "Please enter the wallet passphrase" },
+                    "id": Null,
+                }).to_string());
+        }).await;
+
+        let err = client(&server)
+            .getbalance()
+            .await
+            .expect_err("Expected an RPC error");
+
+        mock.assert_async().await;
+        assert!(matches!(err, ZRpcError::WalletLocked(_)));
+    }
+}
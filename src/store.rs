@@ -0,0 +1,449 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{anyhow, Context, Error};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::{Serialize, Deserialize};
+use crate::chunk::{Chunk, MessageId};
+use crate::files::{config_dir, write_atomic};
+use crate::hex::Memo;
+use crate::inbox::{Body, Message};
+use crate::network::Network;
+use crate::rpc::Tx;
+
+/// Blocks this deep are taken never to be reorganized away, so notes in
+/// them need not be scanned again.
+pub const REORG_DEPTH: usize = 10;
+
+/// A received message and what the user has done with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub message: Message,
+    #[serde(default)]
+    pub read: bool,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl Entry {
+    /// Whether `check` should show the message without `--all`.
+    pub fn is_unread(&self) -> bool {
+        !self.read && !self.archived
+    }
+}
+
+/// Received messages cached as JSON, keyed by the txid and output index
+/// of their first note, in the order they were first seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Store {
+    path: PathBuf,
+    scanned_height: Option<usize>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoreFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scanned_height: Option<usize>,
+    messages: Vec<Entry>,
+}
+
+impl Store {
+    /// Load the store at `path`, which may not exist yet.
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let file = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<StoreFile>(&json)
+                .with_context(|| format!("Failed to parse messages in {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                StoreFile { scanned_height: None, messages: vec![] }
+            },
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read messages from {}", path.display()));
+            },
+        };
+        Ok(Store { path, scanned_height: file.scanned_height, entries: file.messages })
+    }
+
+    /// Load the store of `network` from the zmsg config directory, so that
+    /// wallets on different networks don't share messages.
+    pub fn load_default(network: Network) -> Result<Self, Error> {
+        Self::load(config_dir()?.join(format!("messages-{}.json", network)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let file = StoreFile {
+            scanned_height: self.scanned_height,
+            messages: self.entries.clone(),
+        };
+        write_atomic(&self.path, &serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Failed to write messages to {}", self.path.display()))
+    }
+
+    /// Merge freshly assembled messages. New ones are added unread; known
+    /// ones keep their flags but take the new content, as more parts of a
+    /// long message may have arrived. A long message is known by its id,
+    /// and keeps the txid it was first stored under whichever part comes
+    /// first now. A long message seen incomplete is unread again once it
+    /// is whole. Returns the number of new messages.
+    pub fn sync(&mut self, messages: Vec<Message>) -> usize {
+        let mut added = 0;
+        for message in messages {
+            let known = message.msg_id
+                .and_then(|id| self.entries.iter().position(|entry| entry.message.msg_id == Some(id)))
+                .or_else(|| self.position(&message.txid, message.outindex));
+            match known {
+                Some(i) => {
+                    let entry = &mut self.entries[i];
+                    if matches!(entry.message.body, Body::Incomplete { .. })
+                        && !matches!(message.body, Body::Incomplete { .. })
+                    {
+                        entry.read = false;
+                    }
+                    let txid = std::mem::take(&mut entry.message.txid);
+                    entry.message = Message { txid, outindex: entry.message.outindex, ..message };
+                },
+                None => {
+                    self.entries.push(Entry { message, read: false, archived: false });
+                    added += 1;
+                },
+            }
+        }
+        added
+    }
+
+    /// Height up to which every received note is already in the store.
+    pub fn scanned_height(&self) -> Option<usize> {
+        self.scanned_height
+    }
+
+    /// The notes a scan has to assemble: those not mined yet, and those
+    /// from `since` on or, without it, above the watermark. Earlier parts
+    /// of a long message come along with any later one, so that it is
+    /// never put together from its later parts alone.
+    pub fn unscanned(&self, notes: Vec<(Tx, String)>, since: Option<Since>) -> Vec<(Tx, String)> {
+        let is_new = |tx: &Tx| match (tx.blockheight, since, self.scanned_height) {
+            (None, _, _) => true,
+            (Some(height), Some(since), _) => since.includes(height, tx.blocktime.unwrap_or(usize::MAX)),
+            (Some(height), None, Some(scanned)) => height > scanned,
+            (Some(_), None, None) => true,
+        };
+        let new_parts = notes.iter()
+            .filter(|(tx, _)| is_new(tx))
+            .filter_map(|(tx, _)| message_id(tx))
+            .collect::<HashSet<_>>();
+        notes.into_iter()
+            .filter(|(tx, _)| is_new(tx) || message_id(tx).is_some_and(|id| new_parts.contains(&id)))
+            .collect()
+    }
+
+    /// Drop messages zcashd no longer returns although they had not
+    /// settled: pending ones that left the mempool, and ones in blocks less
+    /// than `REORG_DEPTH` deep that were reorganized away. `notes` must be
+    /// every note of the wallet, pending ones included. Returns the number
+    /// of messages dropped.
+    pub fn prune(&mut self, notes: &[(Tx, String)], tip: usize) -> usize {
+        let returned = notes.iter()
+            .map(|(tx, _)| (tx.txid.as_str(), tx.outindex))
+            .collect::<HashSet<_>>();
+        let before = self.entries.len();
+        self.entries.retain(|entry| {
+            let msg = &entry.message;
+            msg.height.is_some_and(|height| height + REORG_DEPTH <= tip)
+                || returned.contains(&(msg.txid.as_str(), msg.outindex))
+        });
+        before - self.entries.len()
+    }
+
+    /// Record that the chain was scanned up to `tip`. The watermark stays
+    /// `REORG_DEPTH` blocks behind the tip, and below the first part of any
+    /// incomplete message, whose parts must all be scanned again to put it
+    /// together.
+    pub fn advance(&mut self, tip: usize) {
+        let settled = tip.saturating_sub(REORG_DEPTH);
+        let incomplete = self.entries.iter()
+            .filter(|entry| matches!(entry.message.body, Body::Incomplete { .. }))
+            .filter_map(|entry| entry.message.height)
+            .map(|height| height.saturating_sub(1))
+            .min();
+        self.scanned_height = Some(incomplete.map_or(settled, |h| h.min(settled)));
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [Entry] {
+        &mut self.entries
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.entries.iter().map(|entry| &entry.message)
+    }
+
+    pub fn position(&self, txid: &str, outindex: usize) -> Option<usize> {
+        self.entries.iter()
+            .position(|entry| entry.message.txid == txid && entry.message.outindex == outindex)
+    }
+
+    /// Time of a transaction already known from a complete message, to
+    /// spare a `gettransaction` call.
+    pub fn time_of(&self, txid: &str) -> Option<usize> {
+        self.entries.iter()
+            .find(|entry| entry.message.txid == txid && !matches!(entry.message.body, Body::Incomplete { .. }))
+            .map(|entry| entry.message.time)
+    }
+}
+
+/// Id of the long message a note carries a part of.
+fn message_id(tx: &Tx) -> Option<MessageId> {
+    match Memo::from_hex(&tx.memo) {
+        Ok(Memo::Arbitrary(data)) => Chunk::decode(&data).map(|chunk| chunk.msg_id),
+        _ => None,
+    }
+}
+
+/// Where a scan starts: a block height, or a time in seconds since the
+/// epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Since {
+    Height(usize),
+    Time(usize),
+}
+
+impl Since {
+    /// Whether a note mined at `height`, in a block of time `blocktime`,
+    /// falls in the scan.
+    pub fn includes(&self, height: usize, blocktime: usize) -> bool {
+        match *self {
+            Since::Height(since) => height >= since,
+            Since::Time(since) => blocktime >= since,
+        }
+    }
+}
+
+impl FromStr for Since {
+    type Err = Error;
+
+    /// Parse a block height, a local date as `YYYY-MM-DD`, or an RFC 3339
+    /// date and time.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(height) = s.parse() {
+            return Ok(Since::Height(height));
+        }
+        let time = match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(date) => date.and_hms_opt(0, 0, 0)
+                .and_then(|dt| Local.from_local_datetime(&dt).earliest())
+                .map(|dt| dt.timestamp()),
+            Err(_) => DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.timestamp()),
+        };
+        match time {
+            Some(time) if time >= 0 => Ok(Since::Time(time as usize)),
+            _ => Err(anyhow!("Expected a block height or a date such as 2021-05-08, got {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use crate::amount::Amount;
+    use crate::envelope::Envelope;
+    use crate::hex::Memo;
+
+    fn message(txid: &str, body: Body) -> Message {
+        Message {
+            txid: txid.to_string(),
+            outindex: 0,
+            address: "zs1me".to_string(),
+            amount: Amount::from_zat(10_000),
+            time: 100,
+            height: Some(1000),
+            msg_id: None,
+            body,
+        }
+    }
+
+    fn note(txid: &str, height: usize, memo: &Memo) -> (Tx, String) {
+        let tx = serde_json::from_value(serde_json::json!({
+            "txid": txid,
+            "amount": 0.0001,
+            "amountZat": 10_000,
+            "memo": memo.to_hex().unwrap(),
+            "outindex": 0,
+            "confirmations": 1,
+            "blockheight": height,
+            "blocktime": height * 75,
+            "change": false,
+        })).unwrap();
+        (tx, "zs1me".to_string())
+    }
+
+    #[test]
+    fn test_store_sync_and_save() {
+        let dir = env::temp_dir().join(format!("zmsg-store-{}", std::process::id()));
+        let path = dir.join("messages-testnet.json");
+
+        let mut store = Store::load(path.clone()).expect("Missing file should load as empty");
+        let first = vec![
+            message("tx1", Body::Memo(Memo::Text("hello".to_string()))),
+            message("tx2", Body::Incomplete { received: 1, total: 2 }),
+        ];
+        assert!(store.sync(first) == 2);
+        assert!(store.entries().iter().all(Entry::is_unread));
+        store.entries_mut()[0].read = true;
+        store.entries_mut()[1].read = true;
+        assert!(store.time_of("tx1") == Some(100));
+        assert!(store.time_of("tx2").is_none());
+
+        let second = vec![
+            message("tx1", Body::Memo(Memo::Text("hello".to_string()))),
+            message("tx2", Body::Envelope(Envelope::new("now complete").with_reply_to("zs1bob"))),
+            message("tx3", Body::Memo(Memo::Arbitrary(vec![1, 2, 3]))),
+        ];
+        assert!(store.sync(second) == 1);
+        store.save().expect("Failed to save messages");
+
+        let store = Store::load(path).unwrap();
+        let entries = store.entries();
+        assert!(entries.len() == 3);
+        assert!(entries[0].read && !entries[1].read);
+        assert!(entries[1].message.reply_to() == Some("zs1bob"));
+        assert!(store.position("tx3", 0) == Some(2));
+        assert!(store.position("tx3", 1).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_advance() {
+        let path = env::temp_dir().join("zmsg-store-advance").join("messages-testnet.json");
+        let mut store = Store::load(path).unwrap();
+        assert!(store.scanned_height().is_none());
+
+        store.advance(1500);
+        assert!(store.scanned_height() == Some(1500 - REORG_DEPTH));
+
+        // Parts of an incomplete message, the first at height 1000, must
+        // be read again on the next scan.
+        store.sync(vec![message("tx1", Body::Incomplete { received: 1, total: 2 })]);
+        store.advance(1600);
+        assert!(store.scanned_height() == Some(999));
+    }
+
+    #[test]
+    fn test_prune() {
+        let text = Memo::Text("hi".to_string());
+        let at = |txid: &str, height: Option<usize>| Message {
+            height,
+            ..message(txid, Body::Memo(text.clone()))
+        };
+        let path = env::temp_dir().join("zmsg-store-prune").join("messages-testnet.json");
+        let mut store = Store::load(path).unwrap();
+        store.sync(vec![at("tx-settled", Some(1000)), at("tx-pending", None), at("tx-recent", Some(1005))]);
+        let txids = |store: &Store| store.messages().map(|msg| msg.txid.clone()).collect::<Vec<_>>();
+
+        // Everything zcashd still returns stays.
+        let notes = vec![note("tx-pending", 1010, &text), note("tx-recent", 1005, &text)];
+        assert!(store.prune(&notes, 1014) == 0);
+
+        // A pending message that left the mempool is dropped, a settled
+        // one is kept even though zcashd no longer returns it.
+        assert!(store.prune(&notes[1..], 1014) == 1);
+        assert!(txids(&store) == ["tx-settled", "tx-recent"]);
+
+        // So is a recent one whose block was reorganized away, until it
+        // is `REORG_DEPTH` deep.
+        let mut settled = store.clone();
+        assert!(settled.prune(&[], 1015) == 0);
+        assert!(store.prune(&[], 1014) == 1);
+        assert!(txids(&store) == ["tx-settled"]);
+    }
+
+    #[test]
+    fn test_sync_long_message_in_any_order() {
+        let assemble = |notes: &[(Tx, String)]| {
+            crate::inbox::assemble(notes.iter().cloned().map(|(tx, addr)| {
+                let time = tx.blocktime.unwrap_or_default();
+                crate::inbox::Note::received_at(tx, addr, time)
+            }))
+        };
+        let parts = crate::chunk::text_memos(&"a".repeat(700)).unwrap();
+        let first = note("tx1", 100, &parts[0]);
+        let second = note("tx2", 115, &parts[1]);
+
+        let path = env::temp_dir().join("zmsg-store-order").join("messages-testnet.json");
+        let mut store = Store::load(path).unwrap();
+        assert!(store.sync(assemble(std::slice::from_ref(&second))) == 1);
+        assert!(matches!(store.entries()[0].message.body, Body::Incomplete { received: 1, total: 2 }));
+
+        // The rescan sees the first part first, and completes the message
+        // stored under the second one.
+        assert!(store.sync(assemble(&[first.clone(), second.clone()])) == 0);
+        assert!(store.sync(assemble(&[second, first])) == 0);
+        let entries = store.entries();
+        assert!(entries.len() == 1);
+        assert!(entries[0].message.txid == "tx2" && entries[0].is_unread());
+        assert!(entries[0].message.height == Some(100));
+        assert!(!matches!(entries[0].message.body, Body::Incomplete { .. }));
+
+        store.advance(200);
+        assert!(store.scanned_height() == Some(200 - REORG_DEPTH));
+    }
+
+    #[test]
+    fn test_rescan_long_message() {
+        let parts = crate::chunk::text_memos(&"a".repeat(700)).unwrap();
+        assert!(parts.len() == 2);
+        let notes = vec![
+            note("tx0", 50, &Memo::Text("old".to_string())),
+            note("tx1", 100, &parts[0]),
+            note("tx2", 115, &parts[1]),
+        ];
+        let scan = |store: &mut Store, tip: usize| {
+            let notes = store.unscanned(notes.clone(), None);
+            let scanned = notes.iter().map(|(tx, _)| tx.txid.clone()).collect::<Vec<_>>();
+            let notes = notes.into_iter().map(|(tx, addr)| {
+                let time = tx.blocktime.unwrap_or_default();
+                crate::inbox::Note::received_at(tx, addr, time)
+            });
+            store.sync(crate::inbox::assemble(notes));
+            store.advance(tip);
+            scanned
+        };
+
+        let path = env::temp_dir().join("zmsg-store-rescan").join("messages-testnet.json");
+        let mut store = Store::load(path).unwrap();
+        assert!(scan(&mut store, 120) == ["tx0", "tx1", "tx2"]);
+        assert!(store.scanned_height() == Some(110));
+
+        // Only the second part is above the watermark, but the first one
+        // is read again along with it.
+        assert!(scan(&mut store, 121) == ["tx1", "tx2"]);
+        assert!(store.entries().len() == 2);
+        assert!(store.entries().iter().all(|entry| !matches!(entry.message.body, Body::Incomplete { .. })));
+        assert!(store.scanned_height() == Some(111));
+
+        // Once the last part is settled, the message is not read again.
+        scan(&mut store, 130);
+        assert!(store.scanned_height() == Some(120));
+        assert!(scan(&mut store, 131).is_empty());
+    }
+
+    #[test]
+    fn test_since() {
+        assert!("1400000".parse::<Since>().unwrap() == Since::Height(1_400_000));
+        assert!("2021-05-08T23:48:02Z".parse::<Since>().unwrap() == Since::Time(1_620_517_682));
+        assert!(matches!("2021-05-08".parse::<Since>().unwrap(), Since::Time(_)));
+        assert!("last week".parse::<Since>().is_err());
+
+        assert!(Since::Height(100).includes(100, 0));
+        assert!(!Since::Time(1_620_517_682).includes(100, 1_620_517_681));
+    }
+}